@@ -0,0 +1,122 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{ext::IdentExt, parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, LitInt, LitStr, Result};
+
+/// Derive `fuse2rs::FuseDir`, see its documentation for the supported attributes.
+#[proc_macro_derive(FuseDir, attributes(fuse))]
+pub fn derive_fuse_dir(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	expand(input)
+		.unwrap_or_else(Error::into_compile_error)
+		.into()
+}
+
+#[derive(Default)]
+struct Attrs {
+	skip:     bool,
+	dir:      bool,
+	readonly: bool,
+	perm:     Option<LitInt>,
+	rename:   Option<LitStr>,
+}
+
+impl Attrs {
+	fn parse(attrs: &[Attribute]) -> Result<Self> {
+		let mut this = Self::default();
+		for attr in attrs.iter().filter(|a| a.path().is_ident("fuse")) {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("skip") {
+					this.skip = true;
+				} else if meta.path.is_ident("dir") {
+					this.dir = true;
+				} else if meta.path.is_ident("readonly") {
+					this.readonly = true;
+				} else if meta.path.is_ident("perm") {
+					this.perm = Some(meta.value()?.parse()?);
+				} else if meta.path.is_ident("rename") {
+					this.rename = Some(meta.value()?.parse()?);
+				} else {
+					return Err(meta.error("unknown fuse attribute"));
+				}
+				Ok(())
+			})?;
+		}
+		Ok(this)
+	}
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream> {
+	let fields = match &input.data {
+		Data::Struct(s) => {
+			match &s.fields {
+				Fields::Named(fields) => &fields.named,
+				_ => return Err(Error::new_spanned(&input, "FuseDir requires named fields")),
+			}
+		}
+		_ => return Err(Error::new_spanned(&input, "FuseDir can only be derived for structs")),
+	};
+
+	let attrs = Attrs::parse(&input.attrs)?;
+	if attrs.skip || attrs.dir || attrs.perm.is_some() || attrs.rename.is_some() {
+		return Err(Error::new_spanned(&input, "only `readonly` is allowed on the struct"));
+	}
+
+	let mut inserts = Vec::new();
+	for field in fields {
+		let fa = Attrs::parse(&field.attrs)?;
+		if fa.skip {
+			continue;
+		}
+
+		let ident = field.ident.as_ref().unwrap();
+		let ty = &field.ty;
+		let name = match &fa.rename {
+			Some(name) => name.value(),
+			None => ident.unraw().to_string(),
+		};
+		let perm = fa.perm.as_ref().map(|perm| quote!(.perm(#perm)));
+		let lens = quote! {
+			::fuse2rs::__derive::lens({
+				let get = get.clone();
+				move |r| &mut get(r).#ident
+			})
+		};
+
+		inserts.push(if fa.dir {
+			if fa.readonly {
+				return Err(Error::new_spanned(field, "`readonly` has no effect on a directory"));
+			}
+			quote! {
+				let path = path.join(#name);
+				tree.insert(&path, ::fuse2rs::VirtualNode::dir() #perm);
+				<#ty as ::fuse2rs::FuseDir>::insert_into(tree, &path, root, #lens);
+			}
+		} else {
+			let func = if attrs.readonly || fa.readonly {
+				format_ident!("field")
+			} else {
+				format_ident!("field_mut")
+			};
+			quote! {
+				tree.insert(path.join(#name), ::fuse2rs::__derive::#func(root, #lens) #perm);
+			}
+		});
+	}
+
+	let name = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::fuse2rs::FuseDir for #name #ty_generics #where_clause {
+			fn insert_into<R: ::std::marker::Send + 'static>(
+				tree: &mut ::fuse2rs::VirtualTree,
+				path: &::std::path::Path,
+				root: &::std::sync::Arc<::std::sync::Mutex<R>>,
+				get: ::fuse2rs::Lens<R, Self>,
+			) {
+				// every field may be skipped
+				let _ = (&tree, path, root, &get);
+				#({ #inserts })*
+			}
+		}
+	})
+}
@@ -0,0 +1,102 @@
+use std::{
+	ffi::CString,
+	io::{Error, Result},
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+};
+
+use fuse2rs::*;
+
+/// A filesystem containing a single file and the directories leading to it,
+/// for the examples that can't use a `VirtualTree`.
+///
+/// The contents of the file are returned by a closure, which may borrow or share its data.
+pub struct OneFile<C> {
+	path:     PathBuf,
+	contents: C,
+}
+
+impl<C: FnMut() -> Vec<u8>> OneFile<C> {
+	pub fn new(path: impl Into<PathBuf>, contents: C) -> Self {
+		Self {
+			path: path.into(),
+			contents,
+		}
+	}
+}
+
+impl<C: FnMut() -> Vec<u8>> Filesystem for OneFile<C> {
+	type Capabilities = ReadOnly;
+	type DirHandle = ();
+	type Handle = Vec<u8>;
+
+	fn getattr(&mut self, _req: &Request, path: &Path) -> Result<FileAttr> {
+		if path == self.path {
+			Ok(FileAttr {
+				kind: FileType::RegularFile,
+				perm: 0o644,
+				size: (self.contents)().len() as u64,
+				..FileAttr::default()
+			})
+		} else if self.path.starts_with(path) {
+			Ok(FileAttr {
+				kind: FileType::Directory,
+				perm: 0o755,
+				nlink: 2,
+				..FileAttr::default()
+			})
+		} else {
+			Err(Error::from_raw_os_error(libc::ENOENT))
+		}
+	}
+
+	fn readdir(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		off: u64,
+		filler: &mut DirFiller,
+		_dh: &mut (),
+		_info: &FileInfo,
+	) -> Result<()> {
+		if off != 0 {
+			return Ok(());
+		}
+
+		// opendir() made sure, that the file is below `path`
+		let child = self.path.strip_prefix(path).unwrap().iter().next().unwrap();
+		filler.push(c".");
+		filler.push(c"..");
+		filler.push(&CString::new(child.as_bytes()).unwrap());
+		Ok(())
+	}
+
+	fn read(
+		&mut self,
+		_req: &Request,
+		_path: &Path,
+		off: u64,
+		buf: &mut [u8],
+		fh: &mut Vec<u8>,
+		_info: &FileInfo,
+	) -> Result<usize> {
+		let text = fh.get(off as usize..).unwrap_or_default();
+		let len = text.len().min(buf.len());
+		buf[0..len].copy_from_slice(&text[0..len]);
+		Ok(len)
+	}
+
+	fn open(&mut self, _req: &Request, path: &Path, _info: &mut FileInfo) -> Result<Vec<u8>> {
+		match path == self.path {
+			true => Ok((self.contents)()),
+			false => Err(Error::from_raw_os_error(libc::EISDIR)),
+		}
+	}
+
+	fn opendir(&mut self, _req: &Request, path: &Path, _info: &mut FileInfo) -> Result<()> {
+		match path != self.path && self.path.starts_with(path) {
+			true => Ok(()),
+			false => Err(Error::from_raw_os_error(libc::ENOTDIR)),
+		}
+	}
+}
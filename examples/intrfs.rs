@@ -0,0 +1,78 @@
+use std::{
+	io::{Error, Result},
+	path::Path,
+	thread::sleep,
+	time::{Duration, Instant},
+};
+
+use fuse2rs::*;
+
+mod common;
+
+/// A filesystem containing a single file, `/block`, whose reads block until they are interrupted.
+struct Intrfs(common::OneFile<fn() -> Vec<u8>>);
+
+impl Filesystem for Intrfs {
+	type Capabilities = ReadOnly;
+	type DirHandle = ();
+	type Handle = Vec<u8>;
+
+	fn getattr(&mut self, req: &Request, path: &Path) -> Result<FileAttr> {
+		self.0.getattr(req, path)
+	}
+
+	fn readdir(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		filler: &mut DirFiller,
+		dh: &mut (),
+		info: &FileInfo,
+	) -> Result<()> {
+		self.0.readdir(req, path, off, filler, dh, info)
+	}
+
+	fn read(
+		&mut self,
+		req: &Request,
+		_path: &Path,
+		_off: u64,
+		_buf: &mut [u8],
+		_fh: &mut Vec<u8>,
+		_info: &FileInfo,
+	) -> Result<usize> {
+		println!("reading");
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while Instant::now() < deadline {
+			if req.is_interrupted() {
+				println!("interrupted");
+				return Err(Error::from_raw_os_error(libc::EINTR));
+			}
+			sleep(Duration::from_millis(10));
+		}
+		println!("timeout");
+		Ok(0)
+	}
+
+	fn open(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Vec<u8>> {
+		// the file is empty, so the kernel would never read it otherwise
+		info.direct_io = true;
+		self.0.open(req, path, info)
+	}
+
+	fn opendir(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<()> {
+		self.0.opendir(req, path, info)
+	}
+}
+
+fn main() {
+	let mp = std::env::args_os().nth(1).unwrap();
+	let fs = Intrfs(common::OneFile::new("/block", Vec::new as fn() -> _));
+	let opts = vec![
+		MountOption::Foreground,
+		MountOption::AllowOther,
+		MountOption::Intr,
+	];
+	fuse2rs::mount(Path::new(&mp), fs, opts).unwrap();
+}
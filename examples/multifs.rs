@@ -0,0 +1,27 @@
+use std::{
+	io::{stdin, BufRead},
+	path::Path,
+};
+
+use fuse2rs::*;
+
+/// Mount a filesystem on every argument, containing a single file, `/index`,
+/// which holds the index of its mountpoint.
+fn main() {
+	let mut sessions = std::env::args_os()
+		.skip(1)
+		.enumerate()
+		.map(|(i, mp)| {
+			let mut tree = VirtualTree::new();
+			tree.insert("/index", VirtualNode::file(format!("{i}\n")));
+			let opts = vec![MountOption::AllowOther];
+			Some(fuse2rs::spawn_mount(Path::new(&mp), tree, opts).unwrap())
+		})
+		.collect::<Vec<_>>();
+
+	// unmount filesystems by their index, until stdin is closed
+	for line in stdin().lock().lines() {
+		let i = line.unwrap().trim().parse::<usize>().unwrap();
+		sessions[i].take().unwrap().unmount().unwrap();
+	}
+}
@@ -0,0 +1,36 @@
+use std::{
+	io::{Error, ErrorKind},
+	path::Path,
+};
+
+use fuse2rs::*;
+
+/// Drive a mount from a `poll()` loop, until it is unmounted from the outside.
+fn main() {
+	let mp = std::env::args_os().nth(1).unwrap();
+	let mut tree = VirtualTree::new();
+	tree.insert("/test", VirtualNode::file("Hello World\n"));
+	let mut session = Session::new(Path::new(&mp), tree, vec![MountOption::AllowOther]).unwrap();
+
+	// a real event loop would wait for its other file descriptors as well
+	let mut fds = [libc::pollfd {
+		fd:      session.fd(),
+		events:  libc::POLLIN,
+		revents: 0,
+	}];
+	loop {
+		if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+			let e = Error::last_os_error();
+			if e.kind() == ErrorKind::Interrupted {
+				continue;
+			}
+			panic!("poll(): {e}");
+		}
+
+		if !session.process_one().unwrap() {
+			break;
+		}
+	}
+
+	println!("unmounted");
+}
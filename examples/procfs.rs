@@ -0,0 +1,33 @@
+use std::{
+	path::Path,
+	sync::{Arc, Mutex},
+	time::Instant,
+};
+
+use fuse2rs::*;
+
+fn main() {
+	let mp = std::env::args_os().nth(1).unwrap();
+	let start = Instant::now();
+	let level = Arc::new(Mutex::new(String::from("info\n")));
+	let (r, w) = (level.clone(), level);
+
+	let mut tree = VirtualTree::new();
+	tree.insert("/version", VirtualNode::file(concat!(env!("CARGO_PKG_VERSION"), "\n")))
+		.insert(
+			"/status/uptime",
+			VirtualNode::generated(move || Ok(format!("{}\n", start.elapsed().as_secs()).into_bytes()))
+		)
+		.insert(
+			"/config/log_level",
+			VirtualNode::generated(move || Ok(r.lock().unwrap().clone().into_bytes())).on_write(move |data| {
+				*w.lock().unwrap() = String::from_utf8_lossy(data).into_owned();
+				Ok(())
+			})
+		)
+		.insert("/reload", VirtualNode::control(|_| Ok(())))
+		.insert("/latest", VirtualNode::symlink("version"));
+
+	let opts = vec![MountOption::Foreground];
+	fuse2rs::mount(Path::new(&mp), tree, opts).unwrap();
+}
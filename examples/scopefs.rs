@@ -0,0 +1,14 @@
+use std::{fs, path::Path};
+
+mod common;
+
+/// Mount a filesystem containing a single file, `/text`, whose contents are borrowed from `main()`.
+fn main() {
+	let mp = std::env::args_os().nth(1).unwrap();
+	let text = String::from("borrowed\n");
+	let fs = common::OneFile::new("/text", || text.as_bytes().to_vec());
+
+	let (read, _fs) =
+		fuse2rs::scope(Path::new(&mp), fs, Vec::new(), |mp| fs::read_to_string(mp.join("text"))).unwrap();
+	print!("{}", read.unwrap());
+}
@@ -0,0 +1,36 @@
+use std::{
+	io::{stdin, BufRead},
+	path::Path,
+	sync::{Arc, Mutex},
+};
+
+use fuse2rs::*;
+
+mod common;
+
+/// Mount a filesystem containing a single file, `/dir/file`, which is replaced by each line of stdin.
+fn main() {
+	let mp = std::env::args_os().nth(1).unwrap();
+	let text = Arc::new(Mutex::new(b"0\n".to_vec()));
+	let shared = text.clone();
+	let fs = common::OneFile::new("/dir/file", move || shared.lock().unwrap().clone());
+
+	// cache everything, so that only invalidating makes changes visible
+	let opts = vec![
+		MountOption::AllowOther,
+		MountOption::Custom(c"-oattr_timeout=3600".into()),
+		MountOption::Custom(c"-oentry_timeout=3600".into()),
+	];
+	let session = fuse2rs::spawn_mount(Path::new(&mp), fs, opts).unwrap();
+
+	for line in stdin().lock().lines() {
+		*text.lock().unwrap() = format!("{}\n", line.unwrap()).into_bytes();
+		session
+			.invalidator()
+			.invalidate(Path::new("/dir/file"))
+			.unwrap();
+		println!("ok");
+	}
+
+	session.unmount().unwrap();
+}
@@ -10,6 +10,14 @@ struct Testfs;
 const TEXT: &[u8] = b"Hello World\n";
 
 impl Filesystem for Testfs {
+	type Capabilities = ReadOnly;
+	type Handle = ();
+	type DirHandle = ();
+
+	fn operations(&self) -> Operations {
+		Operations::empty()
+	}
+
 	fn getattr(&mut self, _req: &Request, path: &Path) -> Result<FileAttr> {
 		if path == Path::new("/") {
 			Ok(FileAttr {
@@ -37,6 +45,7 @@ impl Filesystem for Testfs {
 		path: &Path,
 		off: u64,
 		filler: &mut DirFiller,
+		_dh: &mut (),
 		_info: &FileInfo,
 	) -> Result<()> {
 		if path != Path::new("/") {
@@ -60,6 +69,7 @@ impl Filesystem for Testfs {
 		path: &Path,
 		off: u64,
 		buf: &mut [u8],
+		_fh: &mut (),
 		_info: &FileInfo,
 	) -> Result<usize> {
 		if path != Path::new("/test") {
@@ -82,6 +92,14 @@ impl Filesystem for Testfs {
 
 		Ok(len)
 	}
+
+	fn open(&mut self, _req: &Request, _path: &Path, _info: &mut FileInfo) -> Result<()> {
+		Ok(())
+	}
+
+	fn opendir(&mut self, _req: &Request, _path: &Path, _info: &mut FileInfo) -> Result<()> {
+		Ok(())
+	}
 }
 
 fn main() {
@@ -0,0 +1,66 @@
+use std::{
+	io::{Error, Result},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+		Mutex,
+	},
+	task::Waker,
+};
+
+/// Signals that a request was interrupted, see `Request::cancellation_token()`.
+///
+/// Clones share their state, so a token can be handed to other threads or tasks,
+/// which either poll it or register a waker to be woken, once it is cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+	inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+	cancelled: AtomicBool,
+	wakers:    Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Cancel the token and wake all registered wakers.
+	pub fn cancel(&self) {
+		if self.inner.cancelled.swap(true, Ordering::AcqRel) {
+			return;
+		}
+
+		let wakers = std::mem::take(&mut *self.inner.wakers.lock().unwrap());
+		for waker in wakers {
+			waker.wake();
+		}
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.inner.cancelled.load(Ordering::Acquire)
+	}
+
+	/// Fail with `EINTR`, if the token was cancelled.
+	pub fn check(&self) -> Result<()> {
+		if self.is_cancelled() {
+			Err(Error::from_raw_os_error(libc::EINTR))
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Wake `waker`, once the token is cancelled, or right away, if it already is.
+	pub fn register(&self, waker: &Waker) {
+		let mut wakers = self.inner.wakers.lock().unwrap();
+		if self.is_cancelled() {
+			drop(wakers);
+			waker.wake_by_ref();
+		} else if !wakers.iter().any(|w| w.will_wake(waker)) {
+			wakers.push(waker.clone());
+		}
+	}
+}
@@ -0,0 +1,72 @@
+use crate::{ll::fuse2, Operations};
+
+/// Declares which capability traits a filesystem implements, see `Filesystem::Capabilities`.
+///
+/// Declaring a capability trait that isn't implemented fails to compile, once the filesystem is mounted.
+pub struct Caps<const WRITE: bool, const NAMESPACE: bool, const XATTR: bool, const LOCK: bool>;
+
+/// Only the operations of `Filesystem`.
+pub type ReadOnly = Caps<false, false, false, false>;
+
+/// `FilesystemWrite` and `FilesystemNamespace`.
+pub type ReadWrite = Caps<true, true, false, false>;
+
+/// The operations that can be registered for `F`.
+///
+/// Implemented by `Caps`, if `F` implements every capability trait it declares.
+pub trait CapabilitySet<F> {
+	#[doc(hidden)]
+	fn register(ops: Operations, table: &mut OperationTable);
+}
+
+#[doc(hidden)]
+pub struct OperationTable(pub(crate) fuse2::fuse_operations);
+
+#[doc(hidden)]
+pub trait Register<F> {
+	fn register(ops: Operations, table: &mut OperationTable);
+}
+
+#[doc(hidden)]
+pub struct WriteCap<const ENABLED: bool>;
+
+#[doc(hidden)]
+pub struct NamespaceCap<const ENABLED: bool>;
+
+#[doc(hidden)]
+pub struct XattrCap<const ENABLED: bool>;
+
+#[doc(hidden)]
+pub struct LockCap<const ENABLED: bool>;
+
+impl<F, const W: bool, const N: bool, const X: bool, const L: bool> CapabilitySet<F> for Caps<W, N, X, L>
+where
+	WriteCap<W>: Register<F>,
+	NamespaceCap<N>: Register<F>,
+	XattrCap<X>: Register<F>,
+	LockCap<L>: Register<F>,
+{
+	fn register(ops: Operations, table: &mut OperationTable) {
+		WriteCap::<W>::register(ops, table);
+		NamespaceCap::<N>::register(ops, table);
+		XattrCap::<X>::register(ops, table);
+		LockCap::<L>::register(ops, table);
+	}
+}
+
+// the enabled capabilities are registered by `ll`
+impl<F> Register<F> for WriteCap<false> {
+	fn register(_ops: Operations, _table: &mut OperationTable) {}
+}
+
+impl<F> Register<F> for NamespaceCap<false> {
+	fn register(_ops: Operations, _table: &mut OperationTable) {}
+}
+
+impl<F> Register<F> for XattrCap<false> {
+	fn register(_ops: Operations, _table: &mut OperationTable) {}
+}
+
+impl<F> Register<F> for LockCap<false> {
+	fn register(_ops: Operations, _table: &mut OperationTable) {}
+}
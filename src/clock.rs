@@ -0,0 +1,53 @@
+use std::{
+	sync::Mutex,
+	time::{Duration, SystemTime},
+};
+
+/// The source of the current time, see `Request::now()`.
+pub trait Clock: Send + Sync {
+	fn now(&self) -> SystemTime;
+}
+
+/// The system clock, which is used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> SystemTime {
+		SystemTime::now()
+	}
+}
+
+/// A clock that only moves when told to, for testing timestamps.
+#[derive(Debug)]
+pub struct ManualClock {
+	time: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+	pub fn new(time: SystemTime) -> Self {
+		Self {
+			time: Mutex::new(time),
+		}
+	}
+
+	pub fn set(&self, time: SystemTime) {
+		*self.time.lock().unwrap() = time;
+	}
+
+	pub fn advance(&self, d: Duration) {
+		*self.time.lock().unwrap() += d;
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&self) -> SystemTime {
+		*self.time.lock().unwrap()
+	}
+}
+
+impl Default for ManualClock {
+	fn default() -> Self {
+		Self::new(SystemTime::UNIX_EPOCH)
+	}
+}
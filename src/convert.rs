@@ -0,0 +1,188 @@
+use std::{
+	fs::Metadata,
+	os::unix::fs::{FileTypeExt, MetadataExt},
+	time::{Duration, SystemTime},
+};
+
+use cfg_if::cfg_if;
+
+use crate::{FileAttr, FileType};
+
+fn to_time(sec: i64, nsec: i64) -> SystemTime {
+	if sec >= 0 {
+		SystemTime::UNIX_EPOCH + Duration::new(sec as u64, nsec as u32)
+	} else {
+		SystemTime::UNIX_EPOCH - Duration::new(sec.unsigned_abs(), 0) + Duration::new(0, nsec as u32)
+	}
+}
+
+pub(crate) fn from_time(t: SystemTime) -> (i64, i64) {
+	match t.duration_since(SystemTime::UNIX_EPOCH) {
+		Ok(d) => (d.as_secs() as i64, d.subsec_nanos().into()),
+		Err(e) => {
+			let d = e.duration();
+			match d.subsec_nanos() {
+				0 => (-(d.as_secs() as i64), 0),
+				n => (-(d.as_secs() as i64) - 1, (1_000_000_000 - n).into()),
+			}
+		}
+	}
+}
+
+#[allow(clippy::unnecessary_cast)] // mode_t differs between platforms
+impl FileType {
+	/// The file type bits of `st_mode`, e.g. `S_IFREG`.
+	pub fn from_mode(mode: u32) -> Option<Self> {
+		match mode & libc::S_IFMT as u32 {
+			m if m == libc::S_IFREG as u32 => Some(Self::RegularFile),
+			m if m == libc::S_IFDIR as u32 => Some(Self::Directory),
+			m if m == libc::S_IFLNK as u32 => Some(Self::Symlink),
+			m if m == libc::S_IFSOCK as u32 => Some(Self::Socket),
+			m if m == libc::S_IFIFO as u32 => Some(Self::NamedPipe),
+			m if m == libc::S_IFCHR as u32 => Some(Self::CharDevice),
+			m if m == libc::S_IFBLK as u32 => Some(Self::BlockDevice),
+			_ => None,
+		}
+	}
+
+	/// The file type bits for `st_mode`, e.g. `S_IFREG`.
+	pub fn mode(self) -> u32 {
+		(match self {
+			Self::RegularFile => libc::S_IFREG,
+			Self::Directory => libc::S_IFDIR,
+			Self::Symlink => libc::S_IFLNK,
+			Self::Socket => libc::S_IFSOCK,
+			Self::NamedPipe => libc::S_IFIFO,
+			Self::CharDevice => libc::S_IFCHR,
+			Self::BlockDevice => libc::S_IFBLK,
+		}) as u32
+	}
+}
+
+impl From<std::fs::FileType> for FileType {
+	fn from(ft: std::fs::FileType) -> Self {
+		if ft.is_dir() {
+			Self::Directory
+		} else if ft.is_symlink() {
+			Self::Symlink
+		} else if ft.is_socket() {
+			Self::Socket
+		} else if ft.is_fifo() {
+			Self::NamedPipe
+		} else if ft.is_char_device() {
+			Self::CharDevice
+		} else if ft.is_block_device() {
+			Self::BlockDevice
+		} else {
+			Self::RegularFile
+		}
+	}
+}
+
+impl From<&Metadata> for FileAttr {
+	fn from(meta: &Metadata) -> Self {
+		let flags;
+		cfg_if! {
+			if #[cfg(target_os = "openbsd")] {
+				flags = std::os::openbsd::fs::MetadataExt::st_flags(meta);
+			} else if #[cfg(target_os = "freebsd")] {
+				flags = std::os::freebsd::fs::MetadataExt::st_flags(meta);
+			} else {
+				flags = 0;
+			}
+		}
+
+		Self {
+			ino: meta.ino(),
+			size: meta.size(),
+			blocks: meta.blocks(),
+			atime: to_time(meta.atime(), meta.atime_nsec()),
+			mtime: to_time(meta.mtime(), meta.mtime_nsec()),
+			ctime: to_time(meta.ctime(), meta.ctime_nsec()),
+			btime: meta.created().unwrap_or(SystemTime::UNIX_EPOCH),
+			kind: meta.file_type().into(),
+			perm: (meta.mode() & 0o7777) as u16,
+			uid: meta.uid(),
+			gid: meta.gid(),
+			rdev: meta.rdev() as u32,
+			blksize: meta.blksize() as u32,
+			flags,
+			nlink: meta.nlink() as u32,
+		}
+	}
+}
+
+impl From<Metadata> for FileAttr {
+	fn from(meta: Metadata) -> Self {
+		Self::from(&meta)
+	}
+}
+
+#[allow(clippy::unnecessary_cast)] // the field types differ between platforms
+impl From<libc::stat> for FileAttr {
+	fn from(st: libc::stat) -> Self {
+		let btime;
+		let flags;
+		cfg_if! {
+			if #[cfg(any(target_os = "openbsd", target_os = "freebsd"))] {
+				btime = to_time(st.st_birthtime as i64, st.st_birthtime_nsec as i64);
+				flags = st.st_flags as u32;
+			} else {
+				btime = SystemTime::UNIX_EPOCH;
+				flags = 0;
+			}
+		}
+
+		Self {
+			ino: st.st_ino as u64,
+			size: st.st_size as u64,
+			blocks: st.st_blocks as u64,
+			atime: to_time(st.st_atime as i64, st.st_atime_nsec as i64),
+			mtime: to_time(st.st_mtime as i64, st.st_mtime_nsec as i64),
+			ctime: to_time(st.st_ctime as i64, st.st_ctime_nsec as i64),
+			btime,
+			kind: FileType::from_mode(st.st_mode as u32).unwrap_or_default(),
+			perm: (st.st_mode & 0o7777) as u16,
+			uid: st.st_uid,
+			gid: st.st_gid,
+			rdev: st.st_rdev as u32,
+			blksize: st.st_blksize as u32,
+			flags,
+			nlink: st.st_nlink as u32,
+		}
+	}
+}
+
+impl From<&FileAttr> for libc::stat {
+	fn from(attr: &FileAttr) -> Self {
+		let mut st: libc::stat = unsafe { std::mem::zeroed() };
+		let (atime, atime_nsec) = from_time(attr.atime);
+		let (mtime, mtime_nsec) = from_time(attr.mtime);
+		let (ctime, ctime_nsec) = from_time(attr.ctime);
+
+		st.st_ino = attr.ino as _;
+		st.st_size = attr.size as _;
+		st.st_blocks = attr.blocks as _;
+		st.st_atime = atime as _;
+		st.st_atime_nsec = atime_nsec as _;
+		st.st_mtime = mtime as _;
+		st.st_mtime_nsec = mtime_nsec as _;
+		st.st_ctime = ctime as _;
+		st.st_ctime_nsec = ctime_nsec as _;
+		cfg_if! {
+			if #[cfg(any(target_os = "openbsd", target_os = "freebsd"))] {
+				let (btime, btime_nsec) = from_time(attr.btime);
+				st.st_birthtime = btime as _;
+				st.st_birthtime_nsec = btime_nsec as _;
+				st.st_flags = attr.flags as _;
+			}
+		}
+		st.st_mode = (attr.kind.mode() | u32::from(attr.perm)) as _;
+		st.st_nlink = attr.nlink as _;
+		st.st_uid = attr.uid;
+		st.st_gid = attr.gid;
+		st.st_rdev = attr.rdev as _;
+		st.st_blksize = attr.blksize as _;
+		st
+	}
+}
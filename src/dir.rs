@@ -0,0 +1,61 @@
+use std::{ffi::CString, io::Result, os::unix::ffi::OsStrExt, path::Path};
+
+use crate::{DirFiller, FileType};
+
+/// An entry of a `DirSnapshot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+	pub name: CString,
+	pub kind: FileType,
+}
+
+/// A listing of a directory, captured once and then served page by page.
+///
+/// Used as `Filesystem::DirHandle`, the listing is captured by `opendir()`,
+/// served by `readdir()` with `fill()` and freed after `releasedir()`.
+/// Since the offsets are indices into the listing, no entry is skipped or repeated,
+/// even if the directory changes in between calls to `readdir()`.
+#[derive(Debug, Clone, Default)]
+pub struct DirSnapshot {
+	entries: Vec<DirEntry>,
+}
+
+impl DirSnapshot {
+	/// Capture the listing of a directory on another filesystem.
+	///
+	/// Like `std::fs::read_dir()`, this doesn't include `.` and `..`.
+	pub fn read_dir(path: &Path) -> Result<Self> {
+		std::fs::read_dir(path)?
+			.map(|e| {
+				let e = e?;
+				Ok(DirEntry {
+					// file names never contain a NUL byte
+					name: CString::new(e.file_name().as_bytes()).unwrap(),
+					kind: e.file_type()?.into(),
+				})
+			})
+			.collect()
+	}
+
+	pub fn entries(&self) -> &[DirEntry] {
+		&self.entries
+	}
+
+	/// Fill `filler` with the entries starting at `off`, as passed to `readdir()`.
+	pub fn fill(&self, off: u64, filler: &mut DirFiller) {
+		let start = usize::try_from(off).unwrap_or(usize::MAX);
+		for (i, e) in self.entries.iter().enumerate().skip(start) {
+			if !filler.push_at(&e.name, e.kind, i as u64 + 1) {
+				break;
+			}
+		}
+	}
+}
+
+impl FromIterator<DirEntry> for DirSnapshot {
+	fn from_iter<I: IntoIterator<Item = DirEntry>>(iter: I) -> Self {
+		Self {
+			entries: iter.into_iter().collect(),
+		}
+	}
+}
@@ -0,0 +1,88 @@
+use std::{
+	fmt::Display,
+	io::Error,
+	path::Path,
+	str::FromStr,
+	sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{VirtualNode, VirtualTree};
+
+/// Accesses a part of `R`, e.g. a nested struct, see `FuseDir`.
+pub type Lens<R, T> = Arc<dyn for<'a> Fn(&'a mut R) -> &'a mut T + Send + Sync>;
+
+/// A struct, whose fields are exposed as the files of a directory in a `VirtualTree`.
+///
+/// Usually derived with `#[derive(FuseDir)]`, which requires the `derive` feature.
+/// Every field becomes a file, that is read with `Display` and written with `FromStr`,
+/// and fields marked with `#[fuse(dir)]` become subdirectories.
+/// Other attributes are:
+/// - `#[fuse(skip)]`, to not expose a field
+/// - `#[fuse(readonly)]`, on a field or on the struct, which doesn't require `FromStr`
+/// - `#[fuse(perm = 0o600)]`, to override the default permissions
+/// - `#[fuse(rename = "name")]`, to change the name of the file
+pub trait FuseDir: Send + 'static {
+	/// Insert the fields of the `Self` returned by `get` below `path`.
+	fn insert_into<R: Send + 'static>(
+		tree: &mut VirtualTree,
+		path: &Path,
+		root: &Arc<Mutex<R>>,
+		get: Lens<R, Self>,
+	);
+}
+
+impl VirtualTree {
+	/// Expose `value` as the directory `path`, see `FuseDir`.
+	///
+	/// The files read and write `value`, while it is shared with the rest of the program.
+	pub fn insert_dir<T: FuseDir>(&mut self, path: impl AsRef<Path>, value: &Arc<Mutex<T>>) -> &mut Self {
+		let path = path.as_ref();
+		if path.parent().is_some() {
+			self.insert(path, VirtualNode::dir());
+		}
+		T::insert_into(self, path, value, lens(|t| t));
+		self
+	}
+}
+
+#[doc(hidden)]
+pub fn lens<R, T, F>(f: F) -> Lens<R, T>
+where
+	F: for<'a> Fn(&'a mut R) -> &'a mut T + Send + Sync + 'static,
+{
+	Arc::new(f)
+}
+
+#[doc(hidden)]
+pub fn field<R, T>(root: &Arc<Mutex<R>>, get: Lens<R, T>) -> VirtualNode
+where
+	R: Send + 'static,
+	T: Display + 'static,
+{
+	let root = root.clone();
+	VirtualNode::generated(move || Ok(format!("{}\n", get(&mut lock(&root))).into_bytes()))
+}
+
+#[doc(hidden)]
+pub fn field_mut<R, T>(root: &Arc<Mutex<R>>, get: Lens<R, T>) -> VirtualNode
+where
+	R: Send + 'static,
+	T: Display + FromStr + 'static,
+{
+	let node = field(root, get.clone());
+	let root = root.clone();
+	node.on_write(move |data| {
+		let value = std::str::from_utf8(data)
+			.ok()
+			.and_then(|s| s.trim_end_matches('\n').parse().ok())
+			.ok_or_else(|| Error::from_raw_os_error(libc::EINVAL))?;
+		*get(&mut lock(&root)) = value;
+		Ok(())
+	})
+}
+
+// a panic while holding the lock doesn't invalidate a plain value
+fn lock<R>(root: &Mutex<R>) -> MutexGuard<'_, R> {
+	root.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
@@ -0,0 +1,139 @@
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	io::{Error, ErrorKind, Read, Result, Write},
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 8] = b"fuse2rs\x01";
+
+/// Stable inode numbers for a path-based filesystem, see `FileAttr::ino`.
+///
+/// Numbers are assigned on first use and are never reused.
+/// They follow their files across `rename()`, are shared between hard links
+/// and can be saved and loaded, to keep them stable across restarts.
+/// The root directory, `/`, always has the inode number `1`.
+#[derive(Debug, Clone)]
+pub struct InodeTable {
+	next:   u64,
+	inodes: HashMap<PathBuf, u64>,
+}
+
+impl InodeTable {
+	pub fn new() -> Self {
+		Self {
+			next:   2,
+			inodes: HashMap::from([(PathBuf::from("/"), 1)]),
+		}
+	}
+
+	/// The inode number of `path`, which is assigned, if `path` doesn't have one yet.
+	pub fn ino(&mut self, path: &Path) -> u64 {
+		if let Some(&ino) = self.inodes.get(path) {
+			return ino;
+		}
+
+		let ino = self.next;
+		self.next += 1;
+		self.inodes.insert(path.to_owned(), ino);
+		ino
+	}
+
+	/// The inode number of `path`, if it has one.
+	pub fn get(&self, path: &Path) -> Option<u64> {
+		self.inodes.get(path).copied()
+	}
+
+	/// Record that `to` was created as a hard link to `from`, see `FilesystemNamespace::link()`.
+	pub fn link(&mut self, from: &Path, to: &Path) -> u64 {
+		let ino = self.ino(from);
+		self.inodes.insert(to.to_owned(), ino);
+		ino
+	}
+
+	/// Record that `from` was renamed to `to`, see `FilesystemNamespace::rename()`.
+	///
+	/// If `from` is a directory, everything below it moves along with it.
+	/// Whatever `to` referred to before is forgotten.
+	pub fn rename(&mut self, from: &Path, to: &Path) {
+		if from == to {
+			return;
+		}
+
+		self.remove(to);
+		let moved = self
+			.inodes
+			.keys()
+			.filter(|path| path.starts_with(from))
+			.cloned()
+			.collect::<Vec<_>>();
+		for path in moved {
+			let ino = self.inodes.remove(&path).unwrap();
+			let rest = path.strip_prefix(from).unwrap();
+			let path = if rest.as_os_str().is_empty() {
+				to.to_owned()
+			} else {
+				to.join(rest)
+			};
+			self.inodes.insert(path, ino);
+		}
+	}
+
+	/// Forget `path` and everything below it, see `FilesystemNamespace::unlink()`.
+	///
+	/// Other hard links keep their inode number.
+	pub fn remove(&mut self, path: &Path) {
+		self.inodes.retain(|p, _| !p.starts_with(path));
+	}
+
+	/// Save the table to `w`, to be restored by `load()`.
+	pub fn save(&self, mut w: impl Write) -> Result<()> {
+		w.write_all(MAGIC)?;
+		w.write_all(&self.next.to_le_bytes())?;
+		w.write_all(&(self.inodes.len() as u64).to_le_bytes())?;
+		for (path, ino) in &self.inodes {
+			let path = path.as_os_str().as_bytes();
+			w.write_all(&ino.to_le_bytes())?;
+			w.write_all(&(path.len() as u64).to_le_bytes())?;
+			w.write_all(path)?;
+		}
+		Ok(())
+	}
+
+	/// Load a table that was saved by `save()`.
+	pub fn load(mut r: impl Read) -> Result<Self> {
+		let mut magic = [0; MAGIC.len()];
+		r.read_exact(&mut magic)?;
+		if &magic != MAGIC {
+			return Err(Error::new(ErrorKind::InvalidData, "not an inode table"));
+		}
+
+		let next = read_u64(&mut r)?;
+		let len = read_u64(&mut r)?;
+		let mut inodes = HashMap::new();
+		for _ in 0..len {
+			let ino = read_u64(&mut r)?;
+			let size = read_u64(&mut r)?;
+			let mut path = Vec::new();
+			if r.by_ref().take(size).read_to_end(&mut path)? as u64 != size {
+				return Err(Error::from(ErrorKind::UnexpectedEof));
+			}
+			inodes.insert(PathBuf::from(OsStr::from_bytes(&path)), ino);
+		}
+
+		Ok(Self { next, inodes })
+	}
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+	let mut buf = [0; 8];
+	r.read_exact(&mut buf)?;
+	Ok(u64::from_le_bytes(buf))
+}
+
+impl Default for InodeTable {
+	fn default() -> Self {
+		Self::new()
+	}
+}
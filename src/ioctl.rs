@@ -0,0 +1,74 @@
+//! Encoding and decoding of ioctl command numbers, see `Filesystem::ioctl()`.
+//!
+//! These are equivalent to the `_IO()`, `_IOR()`, `_IOW()` and `_IOWR()` macros of the target.
+
+use cfg_if::cfg_if;
+
+cfg_if! {
+	if #[cfg(target_os = "linux")] {
+		const NONE: u32 = 0;
+		const WRITE: u32 = 1 << 30;
+		const READ: u32 = 2 << 30;
+		const SIZE_MASK: u32 = 0x3fff;
+	} else {
+		const NONE: u32 = 0x2000_0000;
+		const WRITE: u32 = 0x8000_0000;
+		const READ: u32 = 0x4000_0000;
+		const SIZE_MASK: u32 = 0x1fff;
+	}
+}
+
+/// The ioctl was issued by a 32-bit process on a 64-bit kernel.
+pub const COMPAT: u32 = 1 << 0;
+
+/// The ioctl was issued on a directory.
+pub const DIR: u32 = 1 << 4;
+
+const fn encode(dir: u32, group: u8, num: u8, size: usize) -> u32 {
+	dir | ((size as u32 & SIZE_MASK) << 16) | ((group as u32) << 8) | num as u32
+}
+
+/// A command without any data.
+pub const fn io(group: u8, num: u8) -> u32 {
+	encode(NONE, group, num, 0)
+}
+
+/// A command that returns `size` bytes to the caller.
+pub const fn ior(group: u8, num: u8, size: usize) -> u32 {
+	encode(READ, group, num, size)
+}
+
+/// A command that passes `size` bytes to the filesystem.
+pub const fn iow(group: u8, num: u8, size: usize) -> u32 {
+	encode(WRITE, group, num, size)
+}
+
+/// A command that passes `size` bytes to the filesystem and returns `size` bytes to the caller.
+pub const fn iowr(group: u8, num: u8, size: usize) -> u32 {
+	encode(READ | WRITE, group, num, size)
+}
+
+/// Whether `cmd` returns data to the caller.
+pub const fn is_read(cmd: u32) -> bool {
+	cmd & READ != 0
+}
+
+/// Whether `cmd` passes data to the filesystem.
+pub const fn is_write(cmd: u32) -> bool {
+	cmd & WRITE != 0
+}
+
+/// The size of the data of `cmd`.
+pub const fn size(cmd: u32) -> usize {
+	((cmd >> 16) & SIZE_MASK) as usize
+}
+
+/// The group (or type) of `cmd`.
+pub const fn group(cmd: u32) -> u8 {
+	(cmd >> 8) as u8
+}
+
+/// The number of `cmd` within its group.
+pub const fn num(cmd: u32) -> u8 {
+	cmd as u8
+}
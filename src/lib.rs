@@ -1,32 +1,249 @@
 use std::{
-	ffi::CString,
+	ffi::{CStr, CString, OsStr, OsString},
 	io::{Error, Result},
-	path::Path,
+	mem::MaybeUninit,
+	ops::{BitOr, BitOrAssign, Sub},
+	os::{fd::BorrowedFd, unix::ffi::OsStrExt},
+	path::{Path, PathBuf},
+	sync::{Arc, OnceLock},
 	time::SystemTime,
 };
 
-use libc::{gid_t, mode_t, uid_t};
+use libc::{gid_t, mode_t, pid_t, uid_t};
 
+mod cancel;
+mod caps;
+mod clock;
+mod convert;
+mod dir;
+mod fusedir;
+mod inode;
+pub mod ioctl;
 mod ll;
+mod pathlock;
+mod session;
+mod tree;
 
-pub use crate::ll::DirFiller;
+#[doc(hidden)]
+pub use crate::caps::{LockCap, NamespaceCap, OperationTable, Register, WriteCap, XattrCap};
+pub use crate::{
+	cancel::CancellationToken,
+	caps::{CapabilitySet, Caps, ReadOnly, ReadWrite},
+	clock::{Clock, ManualClock, SystemClock},
+	dir::{DirEntry, DirSnapshot},
+	fusedir::{FuseDir, Lens},
+	inode::InodeTable,
+	ll::{DirFiller, PollHandle, WriteBuf},
+	pathlock::{PathGuard, PathLocks},
+	session::{BackgroundSession, Invalidator, Session},
+	tree::{VirtualNode, VirtualTree},
+};
+#[cfg(feature = "derive")]
+pub use fuse2rs_derive::FuseDir;
+
+#[doc(hidden)]
+pub mod __derive {
+	pub use crate::fusedir::{field, field_mut, lens};
+}
 
 pub struct Request {
 	pub uid:   uid_t,
 	pub gid:   gid_t,
 	pub umask: mode_t,
+	clock:     Arc<dyn Clock>,
+	token:     OnceLock<CancellationToken>,
+	/// Created by `new()`, so there is no libfuse context to query.
+	detached:  bool,
+}
+
+impl Request {
+	/// A request on behalf of `uid` and `gid`, for calling into a filesystem outside of a mount,
+	/// e.g. in tests.
+	pub fn new(uid: uid_t, gid: gid_t, umask: mode_t) -> Self {
+		Self {
+			uid,
+			gid,
+			umask,
+			clock:    Arc::new(SystemClock),
+			token:    OnceLock::new(),
+			detached: true,
+		}
+	}
+
+	/// Replace the clock returned by `clock()`.
+	pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+		self.clock = clock;
+		self
+	}
+
+	/// The clock of the filesystem, see `Filesystem::clock()`.
+	pub fn clock(&self) -> &Arc<dyn Clock> {
+		&self.clock
+	}
+
+	/// The current time, according to `clock()`.
+	///
+	/// Filesystems should use this instead of `SystemTime::now()` for timestamps.
+	pub fn now(&self) -> SystemTime {
+		self.clock.now()
+	}
+
+	/// Whether the caller was interrupted, e.g. by Ctrl-C, and the request should fail with `EINTR`.
+	///
+	/// Must be called on the thread that received the request.
+	/// If it returns `true`, the token returned by `cancellation_token()` is cancelled.
+	/// Always returns `false` on OpenBSD, for requests created with `new()`,
+	/// and for requests processed by `Session::process_one()`.
+	pub fn is_interrupted(&self) -> bool {
+		let intr = !self.detached && crate::ll::interrupted();
+		if intr {
+			if let Some(token) = self.token.get() {
+				token.cancel();
+			}
+		}
+		intr
+	}
+
+	/// A token that is cancelled, once `is_interrupted()` notices that the caller was interrupted.
+	///
+	/// This allows work on other threads or tasks to be cancelled,
+	/// while the thread of the request waits for it and polls `is_interrupted()`.
+	pub fn cancellation_token(&self) -> CancellationToken {
+		let token = self.token.get_or_init(CancellationToken::new).clone();
+		self.is_interrupted();
+		token
+	}
 }
 
 pub struct FileInfo {
-	pub flags:       i32,
-	pub fh:          u64,
+	pub flags:       OpenFlags,
+	/// The write was caused by the kernel writing back a cached page.
+	pub writepage:   bool,
 	pub direct_io:   bool,
 	pub keep_cache:  bool,
 	pub flush:       bool,
 	pub nonseekable: bool,
+	/// The owner of the locks of the file, see `FilesystemLock`.
+	pub lock_owner:  u64,
+}
+
+impl FileInfo {
+	/// The info of a file opened with `flags`, for calling into a filesystem outside of a mount,
+	/// e.g. in tests.
+	pub fn new(flags: OpenFlags) -> Self {
+		Self {
+			flags,
+			writepage: false,
+			direct_io: false,
+			keep_cache: false,
+			flush: false,
+			nonseekable: false,
+			lock_owner: 0,
+		}
+	}
+}
+
+/// The flags passed to `open()`, like `O_APPEND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpenFlags(i32);
+
+impl OpenFlags {
+	pub const fn from_bits(bits: i32) -> Self {
+		Self(bits)
+	}
+
+	pub const fn bits(self) -> i32 {
+		self.0
+	}
+
+	pub const fn access_mode(self) -> AccessMode {
+		match self.0 & libc::O_ACCMODE {
+			libc::O_WRONLY => AccessMode::WriteOnly,
+			libc::O_RDWR => AccessMode::ReadWrite,
+			_ => AccessMode::ReadOnly,
+		}
+	}
+
+	/// Whether the file may be read, i.e. it wasn't opened write-only.
+	pub const fn is_readable(self) -> bool {
+		!matches!(self.access_mode(), AccessMode::WriteOnly)
+	}
+
+	/// Whether the file may be written, i.e. it wasn't opened read-only.
+	pub const fn is_writable(self) -> bool {
+		!matches!(self.access_mode(), AccessMode::ReadOnly)
+	}
+
+	/// `O_APPEND`, every write should go to the end of the file, ignoring its offset.
+	pub const fn append(self) -> bool {
+		self.0 & libc::O_APPEND != 0
+	}
+
+	/// `O_TRUNC`, the file should be truncated.
+	pub const fn truncate(self) -> bool {
+		self.0 & libc::O_TRUNC != 0
+	}
+
+	/// `O_CREAT`, the file is created, if it doesn't exist.
+	pub const fn create(self) -> bool {
+		self.0 & libc::O_CREAT != 0
+	}
+
+	/// `O_EXCL`, creating the file fails, if it already exists.
+	pub const fn exclusive(self) -> bool {
+		self.0 & libc::O_EXCL != 0
+	}
+
+	/// `O_DIRECTORY`, opening the file fails, unless it is a directory.
+	pub const fn directory(self) -> bool {
+		self.0 & libc::O_DIRECTORY != 0
+	}
+
+	/// `O_NOFOLLOW`, opening the file fails, if it is a symbolic link.
+	pub const fn nofollow(self) -> bool {
+		self.0 & libc::O_NOFOLLOW != 0
+	}
+
+	/// `O_NONBLOCK`, I/O on the file shouldn't block.
+	pub const fn nonblock(self) -> bool {
+		self.0 & libc::O_NONBLOCK != 0
+	}
+
+	/// `O_SYNC`, writes should be synchronous.
+	pub const fn sync(self) -> bool {
+		self.0 & libc::O_SYNC != 0
+	}
+}
+
+/// Whether a file was opened for reading, writing or both, see `OpenFlags::access_mode()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+	ReadOnly,
+	WriteOnly,
+	ReadWrite,
 }
 
+/// The operations every filesystem supports.
+///
+/// Further operations are grouped into capability traits, which a filesystem implements
+/// in addition to this one: `FilesystemWrite`, `FilesystemNamespace`, `FilesystemXattr`
+/// and `FilesystemLock`.
+/// Only the operations of the capability traits declared by `Capabilities` are registered with libfuse.
 pub trait Filesystem {
+	/// The capability traits implemented by this filesystem, e.g. `ReadOnly` or `ReadWrite`.
+	///
+	/// A filesystem that wraps another one should declare the capabilities of the inner filesystem,
+	/// and implement each capability trait, if the inner filesystem does.
+	type Capabilities;
+
+	/// The state of an open file, returned by `open()` and `FilesystemNamespace::create()`.
+	///
+	/// It is passed to every operation on the open file and dropped after `release()`.
+	type Handle;
+
+	/// The state of an open directory, returned by `opendir()` and dropped after `releasedir()`.
+	type DirHandle;
+
 	fn getattr(&mut self, _req: &Request, path: &Path) -> Result<FileAttr>;
 
 	fn readdir(
@@ -35,6 +252,7 @@ pub trait Filesystem {
 		path: &Path,
 		off: u64,
 		filler: &mut DirFiller,
+		dh: &mut Self::DirHandle,
 		_info: &FileInfo,
 	) -> Result<()>;
 
@@ -44,37 +262,68 @@ pub trait Filesystem {
 		path: &Path,
 		off: u64,
 		buf: &mut [u8],
+		fh: &mut Self::Handle,
 		_info: &FileInfo,
 	) -> Result<usize>;
 
-	// OPTIONAL
+	fn open(&mut self, _req: &Request, path: &Path, _info: &mut FileInfo) -> Result<Self::Handle>;
 
-	// TODO: KernelConfig
-	fn init(&mut self, _req: &Request) {}
-	fn destroy(&mut self) {}
+	fn opendir(&mut self, _req: &Request, path: &Path, _info: &mut FileInfo) -> Result<Self::DirHandle>;
 
-	fn open(&mut self, _req: &Request, path: &Path, _info: &mut FileInfo) -> Result<()> {
-		let _ = path;
-		Ok(())
+	// OPTIONAL
+
+	/// The set of operations this filesystem implements.
+	///
+	/// Operations that are not part of this set are not registered with libfuse,
+	/// so it falls back to its default behaviour instead of calling into the filesystem.
+	/// For example, without `Operations::CREATE`, files are created with `mknod()` and `open()`.
+	/// The operations of a capability trait are only registered, if it is declared by `Capabilities`.
+	fn operations(&self) -> Operations {
+		Operations::default()
 	}
 
-	fn opendir(&mut self, _req: &Request, path: &Path, _info: &mut FileInfo) -> Result<()> {
-		let _ = path;
-		Ok(())
+	/// The clock used by `Request::now()`, which is queried once when mounting.
+	///
+	/// Times passed to `FilesystemWrite::utime()` for setting a file's timestamps to now
+	/// are also taken from it.
+	fn clock(&self) -> Arc<dyn Clock> {
+		Arc::new(SystemClock)
 	}
 
-	fn release(&mut self, _req: &Request, path: &Path, _info: &FileInfo) -> Result<()> {
-		let _ = path;
+	// TODO: KernelConfig
+	fn init(&mut self, _req: &Request) {}
+	fn destroy(&mut self) {}
+
+	fn release(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		fh: Self::Handle,
+		_info: &FileInfo,
+	) -> Result<()> {
+		let _ = (path, fh);
 		Ok(())
 	}
 
-	fn flush(&mut self, _req: &Request, path: &Path, _info: &FileInfo) -> Result<()> {
-		let _ = path;
+	fn flush(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		_info: &FileInfo,
+	) -> Result<()> {
+		let _ = (path, fh);
 		Ok(())
 	}
 
-	fn releasedir(&mut self, _req: &Request, path: &Path, _info: &FileInfo) -> Result<()> {
-		let _ = path;
+	fn releasedir(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		dh: Self::DirHandle,
+		_info: &FileInfo,
+	) -> Result<()> {
+		let _ = (path, dh);
 		Ok(())
 	}
 
@@ -83,33 +332,102 @@ pub trait Filesystem {
 		Ok(Statfs::default())
 	}
 
-	fn readlink(&mut self, _req: &Request, path: &Path, buf: &mut [u8]) -> Result<()> {
-		let _ = (path, buf);
+	/// Return the target of a symbolic link.
+	///
+	/// The target is truncated, if it doesn't fit into the buffer of the caller.
+	fn readlink(&mut self, _req: &Request, path: &Path) -> Result<PathBuf> {
+		let _ = path;
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
-	fn unlink(&mut self, _req: &Request, path: &Path) -> Result<()> {
-		let _ = path;
+	/// Check whether the file is ready for I/O.
+	///
+	/// If `ph` is `Some`, the filesystem should keep it and call `PollHandle::notify()`,
+	/// once the readiness of the file changes.
+	/// Only supported on FreeBSD and Linux.
+	fn poll(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		_info: &FileInfo,
+		ph: Option<PollHandle>,
+	) -> Result<PollEvents> {
+		let _ = (path, fh, ph);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
-	fn rmdir(&mut self, _req: &Request, path: &Path) -> Result<()> {
-		let _ = path;
-		Err(Error::from_raw_os_error(libc::ENOSYS))
+	/// Handle a restricted ioctl, whose data has a fixed size.
+	///
+	/// `in_data` contains the data passed by the caller, if `cmd` is a `_IOW()` command,
+	/// and `out_data` receives the data returned to the caller, if `cmd` is a `_IOR()` command.
+	/// `flags` is a combination of `ioctl::COMPAT` and `ioctl::DIR`.
+	/// `fh` is `None`, if the ioctl was issued on a directory.
+	/// See the `ioctl` module for how to encode and decode `cmd`.
+	/// Only supported on FreeBSD and Linux.
+	#[allow(clippy::too_many_arguments)]
+	fn ioctl(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		cmd: u32,
+		arg: u64,
+		fh: Option<&mut Self::Handle>,
+		_info: &FileInfo,
+		flags: u32,
+		in_data: &[u8],
+		out_data: &mut [u8],
+	) -> Result<i32> {
+		let _ = (path, cmd, arg, fh, flags, in_data, out_data);
+		Err(Error::from_raw_os_error(libc::ENOTTY))
 	}
 
-	fn mkdir(&mut self, _req: &Request, path: &Path, mode: u32) -> Result<()> {
-		let _ = (path, mode);
+	/// Read up to `size` bytes, starting at `off`.
+	///
+	/// Unlike `read()`, this can return a file descriptor, from which libfuse copies the data,
+	/// preferably without copying it into userspace.
+	/// Only called with `Operations::READ_BUF`, which replaces `read()`.
+	/// Only supported on FreeBSD and Linux.
+	fn read_buf<'a>(
+		&'a mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		size: usize,
+		fh: &'a mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<ReadBuf<'a>> {
+		let mut buf = vec![0; size];
+		let n = self.read(req, path, off, &mut buf, fh, info)?;
+		buf.truncate(n);
+		Ok(ReadBuf::Memory(buf))
+	}
+
+	/// Map the block `idx` of a file, in units of `blocksize`, to a block of the device.
+	/// Only used for filesystems mounted with `MountOption::Blkdev`.
+	fn bmap(&mut self, _req: &Request, path: &Path, blocksize: usize, idx: u64) -> Result<u64> {
+		let _ = (path, blocksize, idx);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
+}
 
-	fn mknod(&mut self, _req: &Request, path: &Path, mode: u32, dev: u32) -> Result<()> {
-		let _ = (path, mode, dev);
+/// Writing to files and changing their attributes.
+pub trait FilesystemWrite: Filesystem {
+	fn write(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &[u8],
+		fh: &mut Self::Handle,
+		_info: &FileInfo,
+	) -> Result<usize> {
+		let _ = (path, off, buf, fh);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
-	fn create(&mut self, _req: &Request, path: &Path, mode: u32, info: &FileInfo) -> Result<()> {
-		let _ = (path, mode, info);
+	fn truncate(&mut self, _req: &Request, path: &Path, size: u64) -> Result<()> {
+		let _ = (path, size);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
@@ -128,20 +446,63 @@ pub trait Filesystem {
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
-	fn write(
+	/// Allocate or deallocate the space of `len` bytes, starting at `off`.
+	/// Only supported on FreeBSD and Linux.
+	#[allow(clippy::too_many_arguments)]
+	fn fallocate(
 		&mut self,
 		_req: &Request,
 		path: &Path,
+		mode: FallocateMode,
 		off: u64,
-		buf: &[u8],
+		len: u64,
+		fh: &mut Self::Handle,
 		_info: &FileInfo,
+	) -> Result<()> {
+		let _ = (path, mode, off, len, fh);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	/// Write the data of `buf`, starting at `off`.
+	///
+	/// Unlike `write()`, the data can be copied directly into a file descriptor,
+	/// preferably without copying it into userspace.
+	/// Only called with `Operations::WRITE_BUF`, which replaces `write()`.
+	/// Only supported on FreeBSD and Linux.
+	fn write_buf(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &mut WriteBuf,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
 	) -> Result<usize> {
-		let _ = (path, off, buf);
+		let mut data = vec![0; buf.len()];
+		let n = buf.copy_to_slice(&mut data)?;
+		self.write(req, path, off, &data[0..n], fh, info)
+	}
+}
+
+/// Creating, removing and renaming files.
+pub trait FilesystemNamespace: Filesystem {
+	fn mknod(&mut self, _req: &Request, path: &Path, mode: u32, dev: u32) -> Result<()> {
+		let _ = (path, mode, dev);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
-	fn link(&mut self, _req: &Request, name1: &Path, name2: &Path) -> Result<()> {
-		let _ = (name1, name2);
+	fn mkdir(&mut self, _req: &Request, path: &Path, mode: u32) -> Result<()> {
+		let _ = (path, mode);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	fn unlink(&mut self, _req: &Request, path: &Path) -> Result<()> {
+		let _ = path;
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	fn rmdir(&mut self, _req: &Request, path: &Path) -> Result<()> {
+		let _ = path;
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
@@ -150,18 +511,579 @@ pub trait Filesystem {
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
+	fn link(&mut self, _req: &Request, name1: &Path, name2: &Path) -> Result<()> {
+		let _ = (name1, name2);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
 	fn rename(&mut self, _req: &Request, from: &Path, to: &Path) -> Result<()> {
 		let _ = (from, to);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 
-	fn truncate(&mut self, _req: &Request, path: &Path, size: u64) -> Result<()> {
-		let _ = (path, size);
+	/// Create and open a file.
+	fn create(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		mode: u32,
+		info: &mut FileInfo,
+	) -> Result<Self::Handle> {
+		let _ = (path, mode, info);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+}
+
+/// Extended attributes.
+pub trait FilesystemXattr: Filesystem {
+	/// Return the value of the extended attribute `name`.
+	fn getxattr(&mut self, _req: &Request, path: &Path, name: &OsStr) -> Result<Vec<u8>> {
+		let _ = (path, name);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	/// Set the extended attribute `name` to `value`.
+	///
+	/// `flags` is either `0`, `XATTR_CREATE` or `XATTR_REPLACE`.
+	fn setxattr(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		name: &OsStr,
+		value: &[u8],
+		flags: i32,
+	) -> Result<()> {
+		let _ = (path, name, value, flags);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	/// Return the names of all extended attributes.
+	fn listxattr(&mut self, _req: &Request, path: &Path) -> Result<Vec<OsString>> {
+		let _ = path;
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	fn removexattr(&mut self, _req: &Request, path: &Path, name: &OsStr) -> Result<()> {
+		let _ = (path, name);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+}
+
+/// POSIX record locks and BSD file locks.
+///
+/// Without this capability, locks are only handled by the kernel,
+/// which is sufficient unless the filesystem is shared over the network.
+pub trait FilesystemLock: Filesystem {
+	/// Test, acquire or release a POSIX record lock.
+	///
+	/// For `LockCmd::Get`, `lock` should be replaced by a conflicting lock,
+	/// or its `kind` set to `LockKind::Unlock`, if there is none.
+	/// Locks are owned by `FileInfo::lock_owner`, not by the process given by `Lock::pid`.
+	fn lock(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		_info: &FileInfo,
+		cmd: LockCmd,
+		lock: &mut Lock,
+	) -> Result<()> {
+		let _ = (path, fh, cmd, lock);
+		Err(Error::from_raw_os_error(libc::ENOSYS))
+	}
+
+	/// Acquire or release a BSD file lock.
+	/// Only supported on FreeBSD and Linux.
+	fn flock(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		_info: &FileInfo,
+		kind: LockKind,
+		nonblock: bool,
+	) -> Result<()> {
+		let _ = (path, fh, kind, nonblock);
 		Err(Error::from_raw_os_error(libc::ENOSYS))
 	}
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+/// Allows mounting a `Box<dyn Filesystem>`, at the cost of dynamic dispatch.
+impl<F: Filesystem + ?Sized> Filesystem for Box<F> {
+	type Capabilities = F::Capabilities;
+	type Handle = F::Handle;
+	type DirHandle = F::DirHandle;
+
+	fn getattr(&mut self, req: &Request, path: &Path) -> Result<FileAttr> {
+		(**self).getattr(req, path)
+	}
+
+	fn readdir(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		filler: &mut DirFiller,
+		dh: &mut Self::DirHandle,
+		info: &FileInfo,
+	) -> Result<()> {
+		(**self).readdir(req, path, off, filler, dh, info)
+	}
+
+	fn read(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &mut [u8],
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<usize> {
+		(**self).read(req, path, off, buf, fh, info)
+	}
+
+	fn open(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Self::Handle> {
+		(**self).open(req, path, info)
+	}
+
+	fn opendir(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Self::DirHandle> {
+		(**self).opendir(req, path, info)
+	}
+
+	fn operations(&self) -> Operations {
+		(**self).operations()
+	}
+
+	fn clock(&self) -> Arc<dyn Clock> {
+		(**self).clock()
+	}
+
+	fn init(&mut self, req: &Request) {
+		(**self).init(req)
+	}
+
+	fn destroy(&mut self) {
+		(**self).destroy()
+	}
+
+	fn release(&mut self, req: &Request, path: &Path, fh: Self::Handle, info: &FileInfo) -> Result<()> {
+		(**self).release(req, path, fh, info)
+	}
+
+	fn flush(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<()> {
+		(**self).flush(req, path, fh, info)
+	}
+
+	fn releasedir(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		dh: Self::DirHandle,
+		info: &FileInfo,
+	) -> Result<()> {
+		(**self).releasedir(req, path, dh, info)
+	}
+
+	fn statfs(&mut self, req: &Request, path: &Path) -> Result<Statfs> {
+		(**self).statfs(req, path)
+	}
+
+	fn readlink(&mut self, req: &Request, path: &Path) -> Result<PathBuf> {
+		(**self).readlink(req, path)
+	}
+
+	fn poll(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+		ph: Option<PollHandle>,
+	) -> Result<PollEvents> {
+		(**self).poll(req, path, fh, info, ph)
+	}
+
+	fn ioctl(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		cmd: u32,
+		arg: u64,
+		fh: Option<&mut Self::Handle>,
+		info: &FileInfo,
+		flags: u32,
+		in_data: &[u8],
+		out_data: &mut [u8],
+	) -> Result<i32> {
+		(**self).ioctl(req, path, cmd, arg, fh, info, flags, in_data, out_data)
+	}
+
+	fn read_buf<'a>(
+		&'a mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		size: usize,
+		fh: &'a mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<ReadBuf<'a>> {
+		(**self).read_buf(req, path, off, size, fh, info)
+	}
+
+	fn bmap(&mut self, req: &Request, path: &Path, blocksize: usize, idx: u64) -> Result<u64> {
+		(**self).bmap(req, path, blocksize, idx)
+	}
+}
+
+impl<F: FilesystemWrite + ?Sized> FilesystemWrite for Box<F> {
+	fn write(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &[u8],
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<usize> {
+		(**self).write(req, path, off, buf, fh, info)
+	}
+
+	fn truncate(&mut self, req: &Request, path: &Path, size: u64) -> Result<()> {
+		(**self).truncate(req, path, size)
+	}
+
+	fn chown(&mut self, req: &Request, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+		(**self).chown(req, path, uid, gid)
+	}
+
+	fn chmod(&mut self, req: &Request, path: &Path, mode: u32) -> Result<()> {
+		(**self).chmod(req, path, mode)
+	}
+
+	fn utime(&mut self, req: &Request, path: &Path, atime: SystemTime, mtime: SystemTime) -> Result<()> {
+		(**self).utime(req, path, atime, mtime)
+	}
+
+	fn fallocate(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		mode: FallocateMode,
+		off: u64,
+		len: u64,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<()> {
+		(**self).fallocate(req, path, mode, off, len, fh, info)
+	}
+
+	fn write_buf(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &mut WriteBuf,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<usize> {
+		(**self).write_buf(req, path, off, buf, fh, info)
+	}
+}
+
+impl<F: FilesystemNamespace + ?Sized> FilesystemNamespace for Box<F> {
+	fn mknod(&mut self, req: &Request, path: &Path, mode: u32, dev: u32) -> Result<()> {
+		(**self).mknod(req, path, mode, dev)
+	}
+
+	fn mkdir(&mut self, req: &Request, path: &Path, mode: u32) -> Result<()> {
+		(**self).mkdir(req, path, mode)
+	}
+
+	fn unlink(&mut self, req: &Request, path: &Path) -> Result<()> {
+		(**self).unlink(req, path)
+	}
+
+	fn rmdir(&mut self, req: &Request, path: &Path) -> Result<()> {
+		(**self).rmdir(req, path)
+	}
+
+	fn symlink(&mut self, req: &Request, name1: &Path, name2: &Path) -> Result<()> {
+		(**self).symlink(req, name1, name2)
+	}
+
+	fn link(&mut self, req: &Request, name1: &Path, name2: &Path) -> Result<()> {
+		(**self).link(req, name1, name2)
+	}
+
+	fn rename(&mut self, req: &Request, from: &Path, to: &Path) -> Result<()> {
+		(**self).rename(req, from, to)
+	}
+
+	fn create(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		mode: u32,
+		info: &mut FileInfo,
+	) -> Result<Self::Handle> {
+		(**self).create(req, path, mode, info)
+	}
+}
+
+impl<F: FilesystemXattr + ?Sized> FilesystemXattr for Box<F> {
+	fn getxattr(&mut self, req: &Request, path: &Path, name: &OsStr) -> Result<Vec<u8>> {
+		(**self).getxattr(req, path, name)
+	}
+
+	fn setxattr(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		name: &OsStr,
+		value: &[u8],
+		flags: i32,
+	) -> Result<()> {
+		(**self).setxattr(req, path, name, value, flags)
+	}
+
+	fn listxattr(&mut self, req: &Request, path: &Path) -> Result<Vec<OsString>> {
+		(**self).listxattr(req, path)
+	}
+
+	fn removexattr(&mut self, req: &Request, path: &Path, name: &OsStr) -> Result<()> {
+		(**self).removexattr(req, path, name)
+	}
+}
+
+impl<F: FilesystemLock + ?Sized> FilesystemLock for Box<F> {
+	fn lock(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+		cmd: LockCmd,
+		lock: &mut Lock,
+	) -> Result<()> {
+		(**self).lock(req, path, fh, info, cmd, lock)
+	}
+
+	fn flock(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+		kind: LockKind,
+		nonblock: bool,
+	) -> Result<()> {
+		(**self).flock(req, path, fh, info, kind, nonblock)
+	}
+}
+
+/// A set of optional filesystem operations, see `Filesystem::operations()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Operations(u64);
+
+impl Operations {
+	pub const FLUSH: Self = Self(1 << 0);
+	pub const STATFS: Self = Self(1 << 1);
+	pub const READLINK: Self = Self(1 << 2);
+	pub const UNLINK: Self = Self(1 << 3);
+	pub const RMDIR: Self = Self(1 << 4);
+	pub const MKDIR: Self = Self(1 << 5);
+	pub const MKNOD: Self = Self(1 << 6);
+	pub const CREATE: Self = Self(1 << 7);
+	pub const CHOWN: Self = Self(1 << 8);
+	pub const CHMOD: Self = Self(1 << 9);
+	pub const UTIME: Self = Self(1 << 10);
+	pub const WRITE: Self = Self(1 << 11);
+	pub const LINK: Self = Self(1 << 12);
+	pub const SYMLINK: Self = Self(1 << 13);
+	pub const RENAME: Self = Self(1 << 14);
+	pub const TRUNCATE: Self = Self(1 << 15);
+	pub const POLL: Self = Self(1 << 16);
+	pub const IOCTL: Self = Self(1 << 17);
+	pub const FALLOCATE: Self = Self(1 << 18);
+	pub const READ_BUF: Self = Self(1 << 19);
+	pub const WRITE_BUF: Self = Self(1 << 20);
+	pub const BMAP: Self = Self(1 << 21);
+	pub const GETXATTR: Self = Self(1 << 22);
+	pub const SETXATTR: Self = Self(1 << 23);
+	pub const LISTXATTR: Self = Self(1 << 24);
+	pub const REMOVEXATTR: Self = Self(1 << 25);
+	pub const LOCK: Self = Self(1 << 26);
+	pub const FLOCK: Self = Self(1 << 27);
+
+	/// No optional operations, only `getattr()`, `readdir()`, `read()`
+	/// and those that open and release files and directories.
+	pub const fn empty() -> Self {
+		Self(0)
+	}
+
+	/// Every operation supported by fuse2rs.
+	pub const fn all() -> Self {
+		Self((1 << 28) - 1)
+	}
+
+	pub const fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+/// Every operation, except for `READ_BUF` and `WRITE_BUF`,
+/// which would replace `read()` and `write()`.
+impl Default for Operations {
+	fn default() -> Self {
+		Self::all() - Self::READ_BUF - Self::WRITE_BUF
+	}
+}
+
+impl BitOr for Operations {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+impl BitOrAssign for Operations {
+	fn bitor_assign(&mut self, rhs: Self) {
+		self.0 |= rhs.0;
+	}
+}
+
+impl Sub for Operations {
+	type Output = Self;
+
+	fn sub(self, rhs: Self) -> Self {
+		Self(self.0 & !rhs.0)
+	}
+}
+
+/// A set of I/O readiness events, see `Filesystem::poll()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PollEvents(u32);
+
+impl PollEvents {
+	pub const ERR: Self = Self(libc::POLLERR as u32);
+	pub const HUP: Self = Self(libc::POLLHUP as u32);
+	pub const IN: Self = Self(libc::POLLIN as u32);
+	pub const NVAL: Self = Self(libc::POLLNVAL as u32);
+	pub const OUT: Self = Self(libc::POLLOUT as u32);
+	pub const PRI: Self = Self(libc::POLLPRI as u32);
+	pub const RDNORM: Self = Self(libc::POLLRDNORM as u32);
+	pub const WRNORM: Self = Self(libc::POLLWRNORM as u32);
+
+	pub const fn empty() -> Self {
+		Self(0)
+	}
+
+	pub const fn bits(self) -> u32 {
+		self.0
+	}
+
+	pub const fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+impl BitOr for PollEvents {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+impl BitOrAssign for PollEvents {
+	fn bitor_assign(&mut self, rhs: Self) {
+		self.0 |= rhs.0;
+	}
+}
+
+impl Sub for PollEvents {
+	type Output = Self;
+
+	fn sub(self, rhs: Self) -> Self {
+		Self(self.0 & !rhs.0)
+	}
+}
+
+/// How `FilesystemWrite::fallocate()` should change a range of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallocateMode {
+	/// Allocate the range, like `posix_fallocate()`.
+	/// Unless `keep_size` is set, the file is extended, if the range ends beyond it.
+	Allocate { keep_size: bool },
+
+	/// Deallocate the range, which then reads as zeroes.
+	/// This never changes the size of the file.
+	PunchHole,
+
+	/// Zero the range, preferably by deallocating it.
+	/// Unless `keep_size` is set, the file is extended, if the range ends beyond it.
+	ZeroRange { keep_size: bool },
+}
+
+/// The kind of a lock, see `FilesystemLock`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+	/// A shared lock.
+	Read,
+
+	/// An exclusive lock.
+	Write,
+
+	/// No lock.
+	Unlock,
+}
+
+/// What `FilesystemLock::lock()` should do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockCmd {
+	/// Return a lock that conflicts with the given one, like `F_GETLK`.
+	Get,
+
+	/// Acquire or release the lock, failing with `EAGAIN` on conflict, like `F_SETLK`.
+	Set,
+
+	/// Acquire or release the lock, waiting for conflicting locks to be released, like `F_SETLKW`.
+	SetWait,
+}
+
+/// A POSIX record lock of `len` bytes, starting at `start`.
+///
+/// A `len` of `0` extends the lock to the end of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lock {
+	pub kind:  LockKind,
+	pub start: u64,
+	pub len:   u64,
+	pub pid:   pid_t,
+}
+
+/// The data returned by `Filesystem::read_buf()`.
+#[derive(Debug)]
+pub enum ReadBuf<'a> {
+	/// Data in memory.
+	Memory(Vec<u8>),
+
+	/// `size` bytes of `fd`, starting at `pos`.
+	Fd {
+		fd:   BorrowedFd<'a>,
+		pos:  u64,
+		size: usize,
+	},
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum FileType {
 	#[default]
 	RegularFile,
@@ -173,16 +1095,86 @@ pub enum FileType {
 	Symlink,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct Statfs {
-	pub bsize:  u32,
-	pub frsize: u32,
-	pub blocks: u64,
-	pub bfree:  u64,
-	pub bavail: u64,
-	pub files:  u64,
-	pub ffree:  u64,
-	pub favail: u64,
+	pub bsize:   u32,
+	pub frsize:  u32,
+	pub blocks:  u64,
+	pub bfree:   u64,
+	pub bavail:  u64,
+	pub files:   u64,
+	pub ffree:   u64,
+	pub favail:  u64,
+	pub fsid:    u64,
+	pub namemax: u32,
+	pub rdonly:  bool,
+	pub nosuid:  bool,
+}
+
+impl Default for Statfs {
+	fn default() -> Self {
+		Self {
+			bsize:   512,
+			frsize:  512,
+			blocks:  0,
+			bfree:   0,
+			bavail:  0,
+			files:   0,
+			ffree:   0,
+			favail:  0,
+			fsid:    0,
+			namemax: 255,
+			rdonly:  false,
+			nosuid:  false,
+		}
+	}
+}
+
+impl Statfs {
+	/// A filesystem of `total` bytes, of which `free` bytes are available,
+	/// divided into blocks of `bsize` bytes.
+	///
+	/// # Panics
+	/// If `bsize` is 0.
+	pub fn from_bytes(total: u64, free: u64, bsize: u32) -> Self {
+		assert!(bsize != 0, "Statfs::from_bytes(): bsize must not be 0");
+		let bfree = free / u64::from(bsize);
+		Self {
+			bsize,
+			frsize: bsize,
+			blocks: total / u64::from(bsize),
+			bfree,
+			bavail: bfree,
+			..Self::default()
+		}
+	}
+
+	/// The statistics of the filesystem containing `path`, see `statvfs(3)`.
+	#[allow(clippy::unnecessary_cast)] // the field types differ between platforms
+	pub fn from_path(path: &Path) -> Result<Self> {
+		let path = CString::new(path.as_os_str().as_bytes())
+			.map_err(|_| Error::from_raw_os_error(libc::EINVAL))?;
+		let mut st = MaybeUninit::<libc::statvfs>::uninit();
+		if unsafe { libc::statvfs(path.as_ptr(), st.as_mut_ptr()) } != 0 {
+			return Err(Error::last_os_error());
+		}
+		let st = unsafe { st.assume_init() };
+
+		Ok(Self {
+			bsize:   st.f_bsize as u32,
+			frsize:  st.f_frsize as u32,
+			blocks:  st.f_blocks,
+			bfree:   st.f_bfree,
+			bavail:  st.f_bavail,
+			files:   st.f_files,
+			ffree:   st.f_ffree,
+			favail:  st.f_favail,
+			fsid:    st.f_fsid as u64,
+			namemax: st.f_namemax as u32,
+			rdonly:  st.f_flag & libc::ST_RDONLY != 0,
+			nosuid:  st.f_flag & libc::ST_NOSUID != 0,
+		})
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -247,9 +1239,19 @@ pub enum MountOption {
 	UseIno,
 	ReaddirIno,
 	HardRemove,
+	/// Interrupt the thread of a request with a signal, when its caller is interrupted,
+	/// see `Request::is_interrupted()`.
+	/// Only supported on FreeBSD and Linux.
+	Intr,
 	Uid(u32),
 	Gid(u32),
 	Umask(u16),
+	/// Mount a filesystem backed by a block device, the device is given by `Fsname`.
+	Blkdev,
+	/// The block size of a `Blkdev` filesystem.
+	Blksize(u32),
+	/// The name of the filesystem, shown as the mounted device.
+	Fsname(String),
 	Custom(CString),
 }
 
@@ -275,15 +1277,89 @@ impl MountOption {
 			Self::UseIno => c"-ouse_ino".into(),
 			Self::ReaddirIno => c"-oreaddir_ino".into(),
 			Self::HardRemove => c"-ohard_remove".into(),
+			Self::Intr => c"-ointr".into(),
 			Self::Uid(uid) => CString::new(format!("-ouid={uid}")).unwrap(),
 			Self::Gid(gid) => CString::new(format!("-ogid={gid}")).unwrap(),
 			Self::Umask(mask) => CString::new(format!("-oumask={mask:o}")).unwrap(),
+			Self::Blkdev => c"-oblkdev".into(),
+			Self::Blksize(size) => CString::new(format!("-oblksize={size}")).unwrap(),
+			Self::Fsname(name) => {
+				let name = name.replace('\\', "\\\\").replace(',', "\\,");
+				CString::new(format!("-ofsname={name}")).unwrap()
+			}
 			Self::Custom(c) => c,
 		}
 	}
 }
 
-pub fn mount(mp: &Path, fs: impl Filesystem + 'static, opts: Vec<MountOption>) -> Result<()> {
+/// Mount `fs` on `mp` and process requests until it is unmounted.
+///
+/// Requests are received by multiple threads, but only one callback runs at a time.
+/// Returns the filesystem after it was unmounted.
+/// To mount a `Box<dyn Filesystem + Send>`, pass it as `F`.
+pub fn mount<F>(mp: &Path, fs: F, opts: Vec<MountOption>) -> Result<F>
+where
+	F: Filesystem + Send,
+	F::Capabilities: CapabilitySet<F>,
+	F::Handle: Send,
+	F::DirHandle: Send,
+{
 	let opts = opts.into_iter().map(|opt| opt.into_cstring()).collect();
-	crate::ll::xmount(mp, Box::new(fs), opts)
+	crate::ll::xmount(mp, fs, opts)
+}
+
+/// Mount `fs` on `mp` and process requests on a new thread.
+///
+/// Unlike `mount()`, this never daemonizes and doesn't install any signal handlers,
+/// so any number of filesystems can be mounted and unmounted independently within one process.
+pub fn spawn_mount<F>(mp: &Path, fs: F, opts: Vec<MountOption>) -> Result<BackgroundSession<F>>
+where
+	F: Filesystem + Send + 'static,
+	F::Capabilities: CapabilitySet<F>,
+	F::Handle: Send,
+	F::DirHandle: Send,
+{
+	Session::new(mp, fs, opts)?.spawn()
+}
+
+/// Mount `fs` on `mp` for the duration of `f`.
+///
+/// Unlike with `mount()`, `f` runs on the current thread, concurrently with the filesystem,
+/// whose requests are processed on a separate thread.
+/// Once `f` returns or panics, the filesystem is always unmounted and that thread is joined,
+/// so unlike with `spawn_mount()`, `fs` may borrow from the caller.
+///
+/// Returns the result of `f` and the filesystem.
+pub fn scope<F, T>(
+	mp: &Path,
+	fs: F,
+	opts: Vec<MountOption>,
+	f: impl FnOnce(&Path) -> T,
+) -> Result<(T, F)>
+where
+	F: Filesystem + Send,
+	F::Capabilities: CapabilitySet<F>,
+	F::Handle: Send,
+	F::DirHandle: Send,
+{
+	struct Unmount<'a>(&'a CStr);
+
+	impl Drop for Unmount<'_> {
+		fn drop(&mut self) {
+			crate::ll::unmount(self.0);
+		}
+	}
+
+	let mut session = Session::new(mp, fs, opts)?;
+	let mpc = session.mountpoint().to_owned();
+
+	std::thread::scope(|s| {
+		let handle = s.spawn(move || session.run().map(|()| session));
+		let guard = Unmount(&mpc);
+		let ret = f(mp);
+		drop(guard);
+
+		let session = handle.join().unwrap()?;
+		Ok((ret, session.unmount()))
+	})
 }
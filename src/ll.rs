@@ -1,14 +1,34 @@
 use std::{
+	collections::HashMap,
 	ffi::*,
 	io::{Error, Result},
 	iter::once,
-	os::unix::ffi::OsStrExt,
+	ops::{Deref, DerefMut},
+	os::{fd::BorrowedFd, unix::ffi::OsStrExt},
 	path::Path,
+	sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock},
 	time::{Duration, SystemTime},
 };
 use cfg_if::cfg_if;
 
-use crate::{FileInfo, FileType, Filesystem, Request};
+use crate::{
+	caps::{LockCap, NamespaceCap, OperationTable, Register, WriteCap, XattrCap},
+	CapabilitySet,
+	Clock,
+	FileInfo,
+	FileType,
+	Filesystem,
+	FilesystemLock,
+	FilesystemNamespace,
+	FilesystemWrite,
+	FilesystemXattr,
+	Lock,
+	LockCmd,
+	LockKind,
+	OpenFlags,
+	Operations,
+	Request,
+};
 
 use self::fuse2::{dev_t, fuse_file_info, fuse_fill_dir_t, gid_t, mode_t, off_t, timespec, uid_t, utimbuf};
 
@@ -19,7 +39,7 @@ use self::fuse2::{dev_t, fuse_file_info, fuse_fill_dir_t, gid_t, mode_t, off_t,
 	non_snake_case,
 	non_upper_case_globals
 )]
-mod fuse2 {
+pub(crate) mod fuse2 {
 	include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
@@ -35,22 +55,298 @@ impl DirFiller {
 			_ => false,
 		}
 	}
+
+	/// Add an entry, where `off` is the offset of the following entry, as passed to `readdir()`.
+	///
+	/// Unlike with `push()`, the listing may be split across multiple calls to `readdir()`.
+	/// Returns `false`, if the buffer is full and `readdir()` should return.
+	pub fn push_at(&mut self, name: &CStr, kind: FileType, off: u64) -> bool {
+		let mut st: fuse2::stat = unsafe { std::mem::zeroed() };
+		st.st_mode = kind.mode() as _;
+
+		match unsafe { self.func.unwrap()(self.data, name.as_ptr(), &st, off as off_t) } {
+			0 => true,
+			_ => false,
+		}
+	}
 }
 
-struct Context {
-	fs: Box<dyn Filesystem>,
+/// A handle for notifying the kernel about a file becoming ready, see `Filesystem::poll()`.
+pub struct PollHandle {
+	ph:    *mut c_void,
+	#[cfg_attr(not(any(target_os = "freebsd", target_os = "linux")), allow(dead_code))]
+	alive: Alive,
 }
 
-pub unsafe fn request() -> (&'static mut dyn Filesystem, Request) {
-	let ctx = &mut *fuse2::fuse_get_context();
-	let data = &mut *(ctx.private_data as *mut Context);
-	let req = Request {
-		uid:   ctx.uid,
-		gid:   ctx.uid,
-		umask: ctx.umask,
-	};
-	(&mut *data.fs, req)
+// libfuse allows notifying from any thread
+unsafe impl Send for PollHandle {}
+unsafe impl Sync for PollHandle {}
+
+impl PollHandle {
+	/// Tell the kernel to poll the file again.
+	///
+	/// Fails with `ENODEV`, once the filesystem was unmounted.
+	pub fn notify(&self) -> Result<()> {
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				// keep the session from being torn down while notifying
+				let alive = self.alive.read().unwrap_or_else(PoisonError::into_inner);
+				if !*alive {
+					return Err(Error::from_raw_os_error(libc::ENODEV));
+				}
+
+				match unsafe { fuse2::fuse_notify_poll(self.ph as *mut fuse2::fuse_pollhandle) } {
+					0 => Ok(()),
+					e => Err(Error::from_raw_os_error(-e)),
+				}
+			} else {
+				Err(Error::from_raw_os_error(libc::ENOSYS))
+			}
+		}
+	}
+}
+
+impl Drop for PollHandle {
+	fn drop(&mut self) {
+		#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+		unsafe {
+			fuse2::fuse_pollhandle_destroy(self.ph as *mut fuse2::fuse_pollhandle)
+		};
+	}
+}
+
+/// The data passed to `FilesystemWrite::write_buf()`.
+///
+/// Each copy continues where the previous one stopped.
+pub struct WriteBuf {
+	bufv: *mut c_void,
+}
+
+impl WriteBuf {
+	/// The size of the data, that hasn't been copied yet.
+	pub fn len(&self) -> usize {
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				// fuse_buf_size() counts the buffers, that were already copied, too
+				// `buf` is a flexible array member, so it must not be accessed through a reference
+				let bufv = self.bufv as *const fuse2::fuse_bufvec;
+				unsafe {
+					let bufs = std::ptr::addr_of!((*bufv).buf) as *const fuse2::fuse_buf;
+					let copied = (0..(*bufv).idx)
+						.map(|i| (*bufs.add(i)).size)
+						.sum::<usize>();
+					fuse2::fuse_buf_size(bufv) - copied - (*bufv).off
+				}
+			} else {
+				0
+			}
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Copy the data into `buf`.
+	pub fn copy_to_slice(&mut self, buf: &mut [u8]) -> Result<usize> {
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				let dst = fuse2::fuse_buf {
+					size:  buf.len(),
+					flags: 0,
+					mem:   buf.as_mut_ptr() as *mut c_void,
+					fd:    -1,
+					pos:   0,
+				};
+				self.copy(dst)
+			} else {
+				let _ = buf;
+				Err(Error::from_raw_os_error(libc::ENOSYS))
+			}
+		}
+	}
+
+	/// Copy the data into `fd`, starting at `pos`, preferably using `splice(2)`.
+	pub fn copy_to_fd(&mut self, fd: BorrowedFd<'_>, pos: u64) -> Result<usize> {
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				let dst = fuse2::fuse_buf {
+					size:  self.len(),
+					flags: fuse2::fuse_buf_flags_FUSE_BUF_IS_FD | fuse2::fuse_buf_flags_FUSE_BUF_FD_SEEK,
+					mem:   std::ptr::null_mut(),
+					fd:    std::os::fd::AsRawFd::as_raw_fd(&fd),
+					pos:   pos as off_t,
+				};
+				self.copy(dst)
+			} else {
+				let _ = (fd, pos);
+				Err(Error::from_raw_os_error(libc::ENOSYS))
+			}
+		}
+	}
+
+	#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+	fn copy(&mut self, dst: fuse2::fuse_buf) -> Result<usize> {
+		let mut dst = fuse2::fuse_bufvec {
+			count: 1,
+			idx:   0,
+			off:   0,
+			buf:   [dst],
+		};
+		let n = unsafe { fuse2::fuse_buf_copy(&mut dst, self.bufv as *mut fuse2::fuse_bufvec, 0) };
+		if n >= 0 {
+			Ok(n as usize)
+		} else {
+			Err(Error::from_raw_os_error(-n as i32))
+		}
+	}
+}
+
+/// The handles of open files or directories.
+///
+/// Ids are never reused, so a stale id can't refer to the handle of another file.
+struct Handles<T> {
+	next: u64,
+	map:  HashMap<u64, T>,
+}
+
+impl<T> Handles<T> {
+	fn new() -> Self {
+		Self {
+			next: 1,
+			map:  HashMap::new(),
+		}
+	}
+
+	fn insert(&mut self, handle: T) -> u64 {
+		let id = self.next;
+		self.next += 1;
+		self.map.insert(id, handle);
+		id
+	}
+
+	fn get_mut(&mut self, id: u64) -> Result<&mut T> {
+		self
+			.map
+			.get_mut(&id)
+			.ok_or_else(|| Error::from_raw_os_error(libc::EBADF))
+	}
+
+	fn remove(&mut self, id: u64) -> Result<T> {
+		self
+			.map
+			.remove(&id)
+			.ok_or_else(|| Error::from_raw_os_error(libc::EBADF))
+	}
+}
+
+/// Whether the session of a mount still exists, see `Context::kill()`.
+type Alive = Arc<RwLock<bool>>;
+
+/// The context of a mount, which is passed to libfuse.
+///
+/// libfuse receives requests on multiple threads, so that one of them can notice
+/// that a request was interrupted, while its callback is still running.
+/// The callbacks themselves take turns, because they get exclusive access to the filesystem.
+pub(crate) type Shared<F> = Mutex<Context<F>>;
+
+pub(crate) struct Context<F: Filesystem> {
+	pub(crate) fs: F,
+	files:         Handles<F::Handle>,
+	dirs:          Handles<F::DirHandle>,
+	clock:         Arc<dyn Clock>,
+	alive:         Alive,
+}
+
+impl<F: Filesystem> Context<F> {
+	pub(crate) fn new(fs: F) -> *mut Shared<F> {
+		let clock = fs.clock();
+		Box::into_raw(Box::new(Mutex::new(Self {
+			fs,
+			clock,
+			files: Handles::new(),
+			dirs: Handles::new(),
+			alive: Arc::new(RwLock::new(true)),
+		})))
+	}
+
+	/// # Safety
+	/// `ctx` must come from `new()`, and libfuse must be done with it.
+	pub(crate) unsafe fn from_raw(ctx: *mut Shared<F>) -> Self {
+		Box::from_raw(ctx)
+			.into_inner()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// # Safety
+	/// `ctx` must come from `new()`, and must not have been passed to `from_raw()`.
+	pub(crate) unsafe fn lock<'a>(ctx: *const Shared<F>) -> MutexGuard<'a, Self> {
+		(*ctx).lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Invalidate all `PollHandle`s, must be called before the session is torn down.
+	pub(crate) fn kill(&self) {
+		*self.alive.write().unwrap_or_else(PoisonError::into_inner) = false;
+	}
+
+	/// The filesystem and the handle of the open file `fh`.
+	fn file(&mut self, fh: u64) -> Result<(&mut F, &mut F::Handle)> {
+		let fh = self.files.get_mut(fh)?;
+		Ok((&mut self.fs, fh))
+	}
+}
+
+/// Exclusive access to the filesystem of a mount, while a callback is running.
+pub struct Locked<'a, F: Filesystem>(MutexGuard<'a, Context<F>>);
+
+impl<F: Filesystem> Deref for Locked<'_, F> {
+	type Target = F;
+
+	fn deref(&self) -> &F {
+		&self.0.fs
+	}
+}
+
+impl<F: Filesystem> DerefMut for Locked<'_, F> {
+	fn deref_mut(&mut self) -> &mut F {
+		&mut self.0.fs
+	}
 }
+
+fn map_request(ctx: &fuse2::fuse_context, clock: &Arc<dyn Clock>) -> Request {
+	Request {
+		uid:      ctx.uid,
+		gid:      ctx.gid,
+		umask:    ctx.umask,
+		clock:    clock.clone(),
+		token:    OnceLock::new(),
+		detached: false,
+	}
+}
+
+unsafe fn context<'a, F: Filesystem>() -> (MutexGuard<'a, Context<F>>, Request) {
+	let ctx = &*fuse2::fuse_get_context();
+	let data = Context::lock(ctx.private_data as *const Shared<F>);
+	let req = map_request(ctx, &data.clock);
+	(data, req)
+}
+
+/// Whether the current request was interrupted.
+pub(crate) fn interrupted() -> bool {
+	cfg_if! {
+		if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+			unsafe { fuse2::fuse_interrupted() != 0 }
+		} else {
+			false
+		}
+	}
+}
+
+pub unsafe fn request<'a, F: Filesystem>() -> (Locked<'a, F>, Request) {
+	let (ctx, req) = context::<F>();
+	(Locked(ctx), req)
+}
+
 fn map_path(path: *const c_char) -> &'static Path {
 	Path::new(OsStr::from_bytes(
 		unsafe { CStr::from_ptr(path) }.to_bytes(),
@@ -58,11 +354,11 @@ fn map_path(path: *const c_char) -> &'static Path {
 }
 
 fn map_time(t: SystemTime) -> fuse2::timespec {
-	let diff = t.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+	let (sec, nsec) = crate::convert::from_time(t);
 
 	fuse2::timespec {
-		tv_sec:  diff.as_secs() as i64,
-		tv_nsec: diff.subsec_nanos() as i64,
+		tv_sec:  sec as _,
+		tv_nsec: nsec as _,
 	}
 }
 
@@ -79,19 +375,19 @@ fn map(r: Result<()>) -> i32 {
 impl From<&fuse2::fuse_file_info> for FileInfo {
 	fn from(info: &fuse2::fuse_file_info) -> Self {
 		Self {
-			fh:          info.fh,
-			flags:       info.flags,
+			flags:       OpenFlags::from_bits(info.flags),
+			writepage:   info.writepage != 0,
 			flush:       info.flush() != 0,
 			direct_io:   info.direct_io() != 0,
 			keep_cache:  info.keep_cache() != 0,
 			nonseekable: info.nonseekable() != 0,
+			lock_owner:  info.lock_owner,
 		}
 	}
 }
 
 impl FileInfo {
 	fn write(&self, info: &mut fuse2::fuse_file_info) {
-		info.fh = self.fh;
 		info.set_flush(self.flush as u32);
 		info.set_direct_io(self.direct_io as u32);
 		info.set_keep_cache(self.keep_cache as u32);
@@ -99,25 +395,15 @@ impl FileInfo {
 	}
 }
 
-unsafe extern "C" fn fs_getattr(path: *const c_char, st: *mut fuse2::stat) -> c_int {
+unsafe extern "C" fn fs_getattr<F: Filesystem>(path: *const c_char, st: *mut fuse2::stat) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 	let st = &mut *st;
 
 	map(
 		fs
 			.getattr(&req, path)
 			.map(|attr| {
-				let kind = match attr.kind {
-					FileType::RegularFile => libc::S_IFREG,
-					FileType::Directory => libc::S_IFDIR,
-					FileType::Symlink => libc::S_IFLNK,
-					FileType::Socket => libc::S_IFSOCK,
-					FileType::NamedPipe => libc::S_IFIFO,
-					FileType::CharDevice => libc::S_IFCHR,
-					FileType::BlockDevice => libc::S_IFBLK,
-				} as u32;
-
 				st.st_ino = attr.ino;
 				st.st_size = attr.size as i64;
 				st.st_blocks = attr.blocks as i64;
@@ -132,7 +418,7 @@ unsafe extern "C" fn fs_getattr(path: *const c_char, st: *mut fuse2::stat) -> c_
 					} else {
 					}
 				}
-				st.st_mode = (kind | attr.perm as u32).try_into().unwrap();
+				st.st_mode = (attr.kind.mode() | attr.perm as u32).try_into().unwrap();
 				st.st_nlink = attr.nlink.try_into().unwrap();
 				st.st_uid = attr.uid;
 				st.st_gid = attr.gid;
@@ -147,7 +433,7 @@ unsafe extern "C" fn fs_getattr(path: *const c_char, st: *mut fuse2::stat) -> c_
 	)
 }
 
-unsafe extern "C" fn fs_readdir(
+unsafe extern "C" fn fs_readdir<F: Filesystem>(
 	path: *const c_char,
 	data: *mut c_void,
 	filler: fuse_fill_dir_t,
@@ -155,15 +441,20 @@ unsafe extern "C" fn fs_readdir(
 	ffi: *mut fuse_file_info,
 ) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
 
 	let mut filler = DirFiller { func: filler, data };
 
 	let info = FileInfo::from(&*ffi);
-	map(fs.readdir(&req, path, off as u64, &mut filler, &info))
+	let dh = match ctx.dirs.get_mut((*ffi).fh) {
+		Ok(dh) => dh,
+		Err(e) => return map_err(e),
+	};
+	map(ctx.fs.readdir(&req, path, off as u64, &mut filler, dh, &info))
 }
 
-unsafe extern "C" fn fs_read(
+unsafe extern "C" fn fs_read<F: Filesystem>(
 	path: *const c_char,
 	buf: *mut c_char,
 	size: usize,
@@ -171,17 +462,21 @@ unsafe extern "C" fn fs_read(
 	ffi: *mut fuse_file_info,
 ) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
 	let info = FileInfo::from(&*ffi);
 	let buf = std::slice::from_raw_parts_mut(buf as *mut u8, size);
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
 
-	match fs.read(&req, path, off as u64, buf, &info) {
+	match fs.read(&req, path, off as u64, buf, fh, &info) {
 		Ok(n) => n as c_int,
 		Err(e) => map_err(e),
 	}
 }
 
-unsafe extern "C" fn fs_write(
+unsafe extern "C" fn fs_write<F: FilesystemWrite>(
 	path: *const c_char,
 	buf: *const c_char,
 	size: usize,
@@ -189,44 +484,58 @@ unsafe extern "C" fn fs_write(
 	ffi: *mut fuse_file_info,
 ) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
 	let info = FileInfo::from(&*ffi);
 	let buf = std::slice::from_raw_parts(buf as *const u8, size);
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
 
-	match fs.write(&req, path, off as u64, buf, &info) {
+	match fs.write(&req, path, off as u64, buf, fh, &info) {
 		Ok(n) => n as c_int,
 		Err(e) => map_err(e),
 	}
 }
 
-unsafe extern "C" fn fs_open(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
+unsafe extern "C" fn fs_open<F: Filesystem>(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
 	let mut info = FileInfo::from(&*ffi);
 
 	map(
-		fs
+		ctx
+			.fs
 			.open(&req, path, &mut info)
-			.map(|_| info.write(&mut *ffi))
+			.map(|fh| {
+				info.write(&mut *ffi);
+				(*ffi).fh = ctx.files.insert(fh);
+			})
 	)
 }
 
-unsafe extern "C" fn fs_opendir(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
+unsafe extern "C" fn fs_opendir<F: Filesystem>(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
 	let mut info = FileInfo::from(&*ffi);
 
 	map(
-		fs
+		ctx
+			.fs
 			.opendir(&req, path, &mut info)
-			.map(|_| info.write(&mut *ffi))
+			.map(|dh| {
+				info.write(&mut *ffi);
+				(*ffi).fh = ctx.dirs.insert(dh);
+			})
 	)
 }
 
-unsafe extern "C" fn fs_statfs(path: *const c_char, st: *mut fuse2::statvfs) -> c_int {
+unsafe extern "C" fn fs_statfs<F: Filesystem>(path: *const c_char, st: *mut fuse2::statvfs) -> c_int {
 	let path = map_path(path);
 	let st = &mut *st;
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(
 		fs
@@ -240,118 +549,170 @@ unsafe extern "C" fn fs_statfs(path: *const c_char, st: *mut fuse2::statvfs) ->
 				st.f_files = s.files;
 				st.f_ffree = s.ffree;
 				st.f_favail = s.favail;
+				st.f_fsid = s.fsid as _;
+				st.f_namemax = s.namemax.into();
+
+				let mut flag = 0;
+				if s.rdonly {
+					flag |= libc::ST_RDONLY;
+				}
+				if s.nosuid {
+					flag |= libc::ST_NOSUID;
+				}
+				st.f_flag = flag as _;
 			})
 	)
 }
 
-unsafe extern "C" fn fs_init(_info: *mut fuse2::fuse_conn_info) -> *mut c_void {
-	let ctx = &mut *fuse2::fuse_get_context();
-	let data = &mut *(ctx.private_data as *mut Context);
-	let req = Request {
-		uid:   ctx.uid,
-		gid:   ctx.uid,
-		umask: ctx.umask,
-	};
-	data.fs.init(&req);
-	ctx.private_data
+unsafe extern "C" fn fs_init<F: Filesystem>(_info: *mut fuse2::fuse_conn_info) -> *mut c_void {
+	let (mut fs, req) = request::<F>();
+	fs.init(&req);
+	(*fuse2::fuse_get_context()).private_data
 }
 
-unsafe extern "C" fn fs_destroy(_ptr: *mut c_void) {
-	let (fs, _req) = request();
+unsafe extern "C" fn fs_destroy<F: Filesystem>(_ptr: *mut c_void) {
+	let (mut fs, _req) = request::<F>();
 	fs.destroy();
 }
 
-unsafe extern "C" fn fs_readlink(path: *const c_char, buf: *mut c_char, size: usize) -> c_int {
+unsafe extern "C" fn fs_readlink<F: Filesystem>(path: *const c_char, buf: *mut c_char, size: usize) -> c_int {
 	let path = map_path(path);
-	let buf = std::slice::from_raw_parts_mut(buf as *mut u8, size);
-	let (fs, req) = request();
+	let buf = match size {
+		0 => &mut [],
+		_ => std::slice::from_raw_parts_mut(buf as *mut u8, size),
+	};
+	let (mut fs, req) = request::<F>();
 
-	map(fs.readlink(&req, path, buf))
+	map(
+		fs
+			.readlink(&req, path)
+			.map(|target| {
+				copy_link(target.as_os_str().as_bytes(), buf);
+			})
+	)
+}
+
+/// Copy the target of a symbolic link into the buffer passed to `readlink()`.
+///
+/// The target is truncated, to always leave space for the NUL-terminator,
+/// and the number of bytes copied before it is returned.
+fn copy_link(target: &[u8], buf: &mut [u8]) -> usize {
+	let len = target.len().min(buf.len().saturating_sub(1));
+	buf[0..len].copy_from_slice(&target[0..len]);
+	if let Some(nul) = buf.get_mut(len) {
+		*nul = 0;
+	}
+	len
 }
 
-unsafe extern "C" fn fs_release(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
+unsafe extern "C" fn fs_release<F: Filesystem>(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
 	let path = map_path(path);
 	let info = FileInfo::from(&*ffi);
-	let (fs, req) = request();
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
 
-	map(fs.release(&req, path, &info))
+	map(
+		ctx
+			.files
+			.remove((*ffi).fh)
+			.and_then(|fh| ctx.fs.release(&req, path, fh, &info))
+	)
 }
 
-unsafe extern "C" fn fs_flush(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
+unsafe extern "C" fn fs_flush<F: Filesystem>(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
 	let path = map_path(path);
 	let info = FileInfo::from(&*ffi);
-	let (fs, req) = request();
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
 
-	map(fs.flush(&req, path, &info))
+	map(fs.flush(&req, path, fh, &info))
 }
 
-unsafe extern "C" fn fs_releasedir(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
+unsafe extern "C" fn fs_releasedir<F: Filesystem>(path: *const c_char, ffi: *mut fuse_file_info) -> c_int {
 	let path = map_path(path);
 	let info = FileInfo::from(&*ffi);
-	let (fs, req) = request();
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
 
-	map(fs.releasedir(&req, path, &info))
+	map(
+		ctx
+			.dirs
+			.remove((*ffi).fh)
+			.and_then(|dh| ctx.fs.releasedir(&req, path, dh, &info))
+	)
 }
 
-unsafe extern "C" fn fs_unlink(path: *const c_char) -> c_int {
+unsafe extern "C" fn fs_unlink<F: FilesystemNamespace>(path: *const c_char) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.unlink(&req, path))
 }
 
-unsafe extern "C" fn fs_rmdir(path: *const c_char) -> c_int {
+unsafe extern "C" fn fs_rmdir<F: FilesystemNamespace>(path: *const c_char) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.rmdir(&req, path))
 }
 
-unsafe extern "C" fn fs_mkdir(path: *const c_char, mode: mode_t) -> c_int {
+unsafe extern "C" fn fs_mkdir<F: FilesystemNamespace>(path: *const c_char, mode: mode_t) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.mkdir(&req, path, mode as u32))
 }
 
-unsafe extern "C" fn fs_mknod(path: *const c_char, mode: mode_t, dev: dev_t) -> c_int {
+unsafe extern "C" fn fs_mknod<F: FilesystemNamespace>(path: *const c_char, mode: mode_t, dev: dev_t) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.mknod(&req, path, mode as u32, dev as u32))
 }
 
-unsafe extern "C" fn fs_create(path: *const c_char, mode: mode_t, ffi: *mut fuse_file_info) -> c_int {
+unsafe extern "C" fn fs_create<F: FilesystemNamespace>(path: *const c_char, mode: mode_t, ffi: *mut fuse_file_info) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
-	let info = FileInfo::from(&*ffi);
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
+	let mut info = FileInfo::from(&*ffi);
 
-	map(fs.create(&req, path, mode as u32, &info))
+	map(
+		ctx
+			.fs
+			.create(&req, path, mode as u32, &mut info)
+			.map(|fh| {
+				info.write(&mut *ffi);
+				(*ffi).fh = ctx.files.insert(fh);
+			})
+	)
 }
 
-unsafe extern "C" fn fs_chown(path: *const c_char, uid: uid_t, gid: gid_t) -> c_int {
+unsafe extern "C" fn fs_chown<F: FilesystemWrite>(path: *const c_char, uid: uid_t, gid: gid_t) -> c_int {
 	let path = map_path(path);
 	let uid = if uid < u32::MAX { Some(uid) } else { None };
 	let gid = if gid < u32::MAX { Some(gid) } else { None };
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.chown(&req, path, uid, gid))
 }
 
-unsafe extern "C" fn fs_chmod(path: *const c_char, mode: mode_t) -> c_int {
+unsafe extern "C" fn fs_chmod<F: FilesystemWrite>(path: *const c_char, mode: mode_t) -> c_int {
 	let path = map_path(path);
 	let mode = mode as u32;
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.chmod(&req, path, mode))
 }
 
-unsafe extern "C" fn fs_utime(path: *const c_char, buf: *mut utimbuf) -> c_int {
+unsafe extern "C" fn fs_utime<F: FilesystemWrite>(path: *const c_char, buf: *mut utimbuf) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	let (at, mt) = if buf.is_null() {
-		let now = SystemTime::now();
+		let now = req.now();
 		(now, now)
 	} else {
 		let buf = &*buf;
@@ -368,12 +729,12 @@ unsafe extern "C" fn fs_utime(path: *const c_char, buf: *mut utimbuf) -> c_int {
 	map(fs.utime(&req, path, at, mt))
 }
 
-unsafe extern "C" fn fs_utimens(path: *const c_char, ts: *const timespec) -> c_int {
+unsafe extern "C" fn fs_utimens<F: FilesystemWrite>(path: *const c_char, ts: *const timespec) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	let (at, mt) = if ts.is_null() {
-		let now = SystemTime::now();
+		let now = req.now();
 		(now, now)
 	} else {
 		let f = |t: timespec| {
@@ -389,117 +750,810 @@ unsafe extern "C" fn fs_utimens(path: *const c_char, ts: *const timespec) -> c_i
 	map(fs.utime(&req, path, at, mt))
 }
 
-unsafe extern "C" fn fs_link(name1: *const c_char, name2: *const c_char) -> c_int {
+unsafe extern "C" fn fs_link<F: FilesystemNamespace>(name1: *const c_char, name2: *const c_char) -> c_int {
 	let name1 = map_path(name1);
 	let name2 = map_path(name2);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.link(&req, name1, name2))
 }
 
-unsafe extern "C" fn fs_symlink(name1: *const c_char, name2: *const c_char) -> c_int {
+unsafe extern "C" fn fs_symlink<F: FilesystemNamespace>(name1: *const c_char, name2: *const c_char) -> c_int {
 	let name1 = map_path(name1);
 	let name2 = map_path(name2);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.symlink(&req, name1, name2))
 }
 
-unsafe extern "C" fn fs_rename(from: *const c_char, to: *const c_char) -> c_int {
+unsafe extern "C" fn fs_rename<F: FilesystemNamespace>(from: *const c_char, to: *const c_char) -> c_int {
 	let from = map_path(from);
 	let to = map_path(to);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.rename(&req, from, to))
 }
 
-unsafe extern "C" fn fs_truncate(path: *const c_char, size: off_t) -> c_int {
+unsafe extern "C" fn fs_truncate<F: FilesystemWrite>(path: *const c_char, size: off_t) -> c_int {
 	let path = map_path(path);
-	let (fs, req) = request();
+	let (mut fs, req) = request::<F>();
 
 	map(fs.truncate(&req, path, size as u64))
 }
 
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+unsafe extern "C" fn fs_poll<F: Filesystem>(
+	path: *const c_char,
+	ffi: *mut fuse_file_info,
+	ph: *mut fuse2::fuse_pollhandle,
+	reventsp: *mut c_uint,
+) -> c_int {
+	let path = map_path(path);
+	let info = FileInfo::from(&*ffi);
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
+	let ph = (!ph.is_null()).then(|| {
+		PollHandle {
+			ph:    ph as *mut c_void,
+			alive: ctx.alive.clone(),
+		}
+	});
+	let fh = match ctx.files.get_mut((*ffi).fh) {
+		Ok(fh) => fh,
+		Err(e) => return map_err(e),
+	};
 
-static FSOPS: fuse2::fuse_operations = fuse2::fuse_operations {
-	access: None,
-	bmap: None,
-	getattr: Some(fs_getattr),
-	readlink: Some(fs_readlink),
-	getdir: None,
-	mknod: Some(fs_mknod),
-	mkdir: Some(fs_mkdir),
-	unlink: Some(fs_unlink),
-	rmdir: Some(fs_rmdir),
-	symlink: Some(fs_symlink),
-	rename: Some(fs_rename),
-	link: Some(fs_link),
-	chmod: Some(fs_chmod),
-	chown: Some(fs_chown),
-	truncate: Some(fs_truncate),
-	utime: Some(fs_utime),
-	open: Some(fs_open),
-	read: Some(fs_read),
-	write: Some(fs_write),
-	statfs: Some(fs_statfs),
-	flush: Some(fs_flush),
-	release: Some(fs_release),
-	fsync: None,
-	setxattr: None,
-	getxattr: None,
-	listxattr: None,
-	removexattr: None,
-	opendir: Some(fs_opendir),
-	readdir: Some(fs_readdir),
-	releasedir: Some(fs_releasedir),
-	fsyncdir: None,
-	init: Some(fs_init),
-	destroy: Some(fs_destroy),
-	create: Some(fs_create),
-	ftruncate: None,
-	fgetattr: None,
-	lock: None,
-	utimens: Some(fs_utimens),
-
-	// this is _very_ ugly
-	..unsafe { std::mem::zeroed() }
-};
+	match ctx.fs.poll(&req, path, fh, &info, ph) {
+		Ok(revents) => {
+			*reventsp = revents.bits();
+			0
+		}
+		Err(e) => map_err(e),
+	}
+}
 
-pub fn xmount(mp: &Path, fs: Box<dyn Filesystem>, opts: Vec<CString>) -> Result<()> {
-	// TODO: this sucks, find something better
-	let mut mp = mp.as_os_str().as_bytes().to_vec();
-	mp.push(b'\0');
-	let Ok(mp) = CString::from_vec_with_nul(mp) else {
-		return Err(Error::from_raw_os_error(libc::EINVAL));
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+unsafe extern "C" fn fs_ioctl<F: Filesystem>(
+	path: *const c_char,
+	cmd: c_int,
+	arg: *mut c_void,
+	ffi: *mut fuse_file_info,
+	flags: c_uint,
+	data: *mut c_void,
+) -> c_int {
+	let path = map_path(path);
+	let cmd = cmd as u32;
+	let info = FileInfo::from(&*ffi);
+	let size = crate::ioctl::size(cmd);
+	let is_read = crate::ioctl::is_read(cmd);
+	let is_write = crate::ioctl::is_write(cmd);
+	let data = data as *mut u8;
+	let empty = data.is_null() || size == 0 || !(is_read || is_write);
+	let copy;
+	let (input, output): (&[u8], &mut [u8]) = if empty {
+		(&[], &mut [])
+	} else if !is_read {
+		// libfuse passes its input buffer, which must not be written to
+		(std::slice::from_raw_parts(data, size), &mut [])
+	} else {
+		if !is_write {
+			// libfuse doesn't initialize the output buffer, but returns all of it to the caller
+			std::ptr::write_bytes(data, 0, size);
+		}
+		let output = std::slice::from_raw_parts_mut(data, size);
+		copy = match is_write {
+			true => output.to_vec(),
+			false => Vec::new(),
+		};
+		(&copy, output)
+	};
+	let (mut guard, req) = context::<F>();
+	let ctx = &mut *guard;
+	let fh = if flags & crate::ioctl::DIR != 0 {
+		None
+	} else {
+		match ctx.files.get_mut((*ffi).fh) {
+			Ok(fh) => Some(fh),
+			Err(e) => return map_err(e),
+		}
 	};
 
-	let ctx = Box::new(Context { fs });
+	match ctx.fs.ioctl(&req, path, cmd, arg as u64, fh, &info, flags, input, output) {
+		Ok(n) => n,
+		Err(e) => map_err(e),
+	}
+}
 
-	let mut args = opts
-		.into_iter()
-		.chain(once(mp))
-		.map(|s| s.into_raw())
-		.chain(once(std::ptr::null_mut()))
-		.collect::<Vec<_>>();
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+fn map_fallocate_mode(mode: c_int) -> Option<crate::FallocateMode> {
+	use crate::FallocateMode;
+
+	// values of the FUSE protocol, not necessarily those of the host
+	const KEEP_SIZE: c_int = 0x01;
+	const PUNCH_HOLE: c_int = 0x02;
+	const ZERO_RANGE: c_int = 0x10;
+
+	let keep_size = mode & KEEP_SIZE != 0;
+	match mode & !KEEP_SIZE {
+		0 => Some(FallocateMode::Allocate { keep_size }),
+		PUNCH_HOLE if keep_size => Some(FallocateMode::PunchHole),
+		ZERO_RANGE => Some(FallocateMode::ZeroRange { keep_size }),
+		_ => None,
+	}
+}
 
-	let argc = args.len() as i32 - 1;
-	let argv = args.as_mut_ptr();
-	let ctx = Box::into_raw(ctx) as *mut c_void;
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+unsafe extern "C" fn fs_fallocate<F: FilesystemWrite>(
+	path: *const c_char,
+	mode: c_int,
+	off: off_t,
+	len: off_t,
+	ffi: *mut fuse_file_info,
+) -> c_int {
+	let path = map_path(path);
+	let Some(mode) = map_fallocate_mode(mode) else {
+		return -libc::EOPNOTSUPP;
+	};
+	let info = FileInfo::from(&*ffi);
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
+
+	map(fs.fallocate(&req, path, mode, off as u64, len as u64, fh, &info))
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+unsafe extern "C" fn fs_read_buf<F: Filesystem>(
+	path: *const c_char,
+	bufp: *mut *mut fuse2::fuse_bufvec,
+	size: usize,
+	off: off_t,
+	ffi: *mut fuse_file_info,
+) -> c_int {
+	use std::os::fd::AsRawFd;
+
+	use crate::ReadBuf;
+
+	let path = map_path(path);
+	let info = FileInfo::from(&*ffi);
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
+
+	let buf = match fs.read_buf(&req, path, off as u64, size, fh, &info) {
+		Ok(ReadBuf::Memory(data)) => {
+			// the kernel rejects replies that are larger than requested
+			let len = data.len().min(size);
+			// libfuse releases the buffers using free()
+			let mem = libc::malloc(len.max(1));
+			if mem.is_null() {
+				return -libc::ENOMEM;
+			}
+			std::ptr::copy_nonoverlapping(data.as_ptr(), mem as *mut u8, len);
+			fuse2::fuse_buf {
+				size: len,
+				flags: 0,
+				mem,
+				fd: -1,
+				pos: 0,
+			}
+		}
+		Ok(ReadBuf::Fd { fd, pos, size: len }) => {
+			fuse2::fuse_buf {
+				size: len.min(size),
+				flags: fuse2::fuse_buf_flags_FUSE_BUF_IS_FD | fuse2::fuse_buf_flags_FUSE_BUF_FD_SEEK,
+				mem: std::ptr::null_mut(),
+				fd: fd.as_raw_fd(),
+				pos: pos as off_t,
+			}
+		}
+		Err(e) => return map_err(e),
+	};
+
+	let bufv = libc::malloc(std::mem::size_of::<fuse2::fuse_bufvec>()) as *mut fuse2::fuse_bufvec;
+	if bufv.is_null() {
+		if buf.fd == -1 {
+			libc::free(buf.mem);
+		}
+		return -libc::ENOMEM;
+	}
+	bufv.write(fuse2::fuse_bufvec {
+		count: 1,
+		idx:   0,
+		off:   0,
+		buf:   [buf],
+	});
+	*bufp = bufv;
+	0
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+unsafe extern "C" fn fs_write_buf<F: FilesystemWrite>(
+	path: *const c_char,
+	bufv: *mut fuse2::fuse_bufvec,
+	off: off_t,
+	ffi: *mut fuse_file_info,
+) -> c_int {
+	let path = map_path(path);
+	let info = FileInfo::from(&*ffi);
+	let mut buf = WriteBuf {
+		bufv: bufv as *mut c_void,
+	};
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
+
+	match fs.write_buf(&req, path, off as u64, &mut buf, fh, &info) {
+		Ok(n) => n as c_int,
+		Err(e) => map_err(e),
+	}
+}
+
+unsafe extern "C" fn fs_bmap<F: Filesystem>(path: *const c_char, blocksize: usize, idx: *mut u64) -> c_int {
+	let path = map_path(path);
+	let (mut fs, req) = request::<F>();
+
+	map(
+		fs
+			.bmap(&req, path, blocksize, *idx)
+			.map(|block| *idx = block)
+	)
+}
+
+unsafe extern "C" fn fs_getxattr<F: FilesystemXattr>(
+	path: *const c_char,
+	name: *const c_char,
+	value: *mut c_char,
+	size: usize,
+) -> c_int {
+	let path = map_path(path);
+	let name = OsStr::from_bytes(CStr::from_ptr(name).to_bytes());
+	let (mut fs, req) = request::<F>();
+
+	match fs.getxattr(&req, path, name) {
+		Ok(data) => copy_xattr(&data, value, size),
+		Err(e) => map_err(e),
+	}
+}
+
+unsafe extern "C" fn fs_setxattr<F: FilesystemXattr>(
+	path: *const c_char,
+	name: *const c_char,
+	value: *const c_char,
+	size: usize,
+	flags: c_int,
+) -> c_int {
+	let path = map_path(path);
+	let name = OsStr::from_bytes(CStr::from_ptr(name).to_bytes());
+	let value = if value.is_null() {
+		&[]
+	} else {
+		std::slice::from_raw_parts(value as *const u8, size)
+	};
+	let (mut fs, req) = request::<F>();
+
+	map(fs.setxattr(&req, path, name, value, flags))
+}
+
+unsafe extern "C" fn fs_listxattr<F: FilesystemXattr>(path: *const c_char, list: *mut c_char, size: usize) -> c_int {
+	let path = map_path(path);
+	let (mut fs, req) = request::<F>();
+
+	match fs.listxattr(&req, path) {
+		Ok(names) => {
+			let data = names
+				.iter()
+				.flat_map(|name| name.as_bytes().iter().copied().chain(once(0)))
+				.collect::<Vec<u8>>();
+			copy_xattr(&data, list, size)
+		}
+		Err(e) => map_err(e),
+	}
+}
+
+unsafe extern "C" fn fs_removexattr<F: FilesystemXattr>(path: *const c_char, name: *const c_char) -> c_int {
+	let path = map_path(path);
+	let name = OsStr::from_bytes(CStr::from_ptr(name).to_bytes());
+	let (mut fs, req) = request::<F>();
+
+	map(fs.removexattr(&req, path, name))
+}
+
+/// Copy `data` into the buffer of the caller, or only return its size, if `size` is zero.
+unsafe fn copy_xattr(data: &[u8], buf: *mut c_char, size: usize) -> c_int {
+	if size == 0 {
+		data.len() as c_int
+	} else if data.len() > size {
+		-libc::ERANGE
+	} else {
+		std::ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, data.len());
+		data.len() as c_int
+	}
+}
+
+#[allow(clippy::unnecessary_cast)] // the lock types are c_short on some platforms
+fn map_lock_kind(kind: c_int) -> Option<LockKind> {
+	match kind {
+		k if k == libc::F_RDLCK as c_int => Some(LockKind::Read),
+		k if k == libc::F_WRLCK as c_int => Some(LockKind::Write),
+		k if k == libc::F_UNLCK as c_int => Some(LockKind::Unlock),
+		_ => None,
+	}
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn lock_type(kind: LockKind) -> c_int {
+	(match kind {
+		LockKind::Read => libc::F_RDLCK,
+		LockKind::Write => libc::F_WRLCK,
+		LockKind::Unlock => libc::F_UNLCK,
+	}) as c_int
+}
+
+unsafe extern "C" fn fs_lock<F: FilesystemLock>(
+	path: *const c_char,
+	ffi: *mut fuse_file_info,
+	cmd: c_int,
+	fl: *mut fuse2::flock,
+) -> c_int {
+	let path = map_path(path);
+	let info = FileInfo::from(&*ffi);
+	let fl = &mut *fl;
+	let cmd = match cmd {
+		libc::F_GETLK => LockCmd::Get,
+		libc::F_SETLK => LockCmd::Set,
+		libc::F_SETLKW => LockCmd::SetWait,
+		_ => return -libc::EINVAL,
+	};
+	let Some(kind) = map_lock_kind(fl.l_type.into()) else {
+		return -libc::EINVAL;
+	};
+	let mut lock = Lock {
+		kind,
+		start: fl.l_start as u64,
+		len: fl.l_len as u64,
+		pid: fl.l_pid,
+	};
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
+
+	map(
+		fs
+			.lock(&req, path, fh, &info, cmd, &mut lock)
+			.map(|()| {
+				fl.l_type = lock_type(lock.kind) as _;
+				fl.l_whence = libc::SEEK_SET as _;
+				fl.l_start = lock.start as off_t;
+				fl.l_len = lock.len as off_t;
+				fl.l_pid = lock.pid;
+			})
+	)
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+unsafe extern "C" fn fs_flock<F: FilesystemLock>(path: *const c_char, ffi: *mut fuse_file_info, op: c_int) -> c_int {
+	let path = map_path(path);
+	let info = FileInfo::from(&*ffi);
+	let nonblock = op & libc::LOCK_NB != 0;
+	let kind = match op & !libc::LOCK_NB {
+		libc::LOCK_SH => LockKind::Read,
+		libc::LOCK_EX => LockKind::Write,
+		libc::LOCK_UN => LockKind::Unlock,
+		_ => return -libc::EINVAL,
+	};
+	let (mut ctx, req) = context::<F>();
+	let (fs, fh) = match ctx.file((*ffi).fh) {
+		Ok(r) => r,
+		Err(e) => return map_err(e),
+	};
+
+	map(fs.flock(&req, path, fh, &info, kind, nonblock))
+}
+
+pub(crate) fn operations<F>(ops: Operations) -> fuse2::fuse_operations
+where
+	F: Filesystem,
+	F::Capabilities: CapabilitySet<F>,
+{
+	let has = |op| ops.contains(op);
+
+	#[allow(unused_mut)]
+	let mut fsops = fuse2::fuse_operations {
+		access: None,
+		bmap: has(Operations::BMAP).then_some(fs_bmap::<F> as _),
+		getattr: Some(fs_getattr::<F>),
+		readlink: has(Operations::READLINK).then_some(fs_readlink::<F> as _),
+		getdir: None,
+		open: Some(fs_open::<F>),
+		read: Some(fs_read::<F>),
+		statfs: has(Operations::STATFS).then_some(fs_statfs::<F> as _),
+		flush: has(Operations::FLUSH).then_some(fs_flush::<F> as _),
+		release: Some(fs_release::<F>),
+		fsync: None,
+		opendir: Some(fs_opendir::<F>),
+		readdir: Some(fs_readdir::<F>),
+		releasedir: Some(fs_releasedir::<F>),
+		fsyncdir: None,
+		init: Some(fs_init::<F>),
+		destroy: Some(fs_destroy::<F>),
+		ftruncate: None,
+		fgetattr: None,
+
+		// the operations of the capability traits are registered below,
+		// this is _very_ ugly
+		..unsafe { std::mem::zeroed() }
+	};
+
+	// not supported by OpenBSD
+	cfg_if! {
+		if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+			fsops.poll = has(Operations::POLL).then_some(fs_poll::<F> as _);
+			fsops.ioctl = has(Operations::IOCTL).then_some(fs_ioctl::<F> as _);
+			fsops.read_buf = has(Operations::READ_BUF).then_some(fs_read_buf::<F> as _);
+		}
+	}
+
+	let mut table = OperationTable(fsops);
+	<F::Capabilities as CapabilitySet<F>>::register(ops, &mut table);
+	table.0
+}
+
+impl<F: FilesystemWrite> Register<F> for WriteCap<true> {
+	fn register(ops: Operations, table: &mut OperationTable) {
+		let has = |op| ops.contains(op);
+		let fsops = &mut table.0;
+
+		fsops.chmod = has(Operations::CHMOD).then_some(fs_chmod::<F> as _);
+		fsops.chown = has(Operations::CHOWN).then_some(fs_chown::<F> as _);
+		fsops.truncate = has(Operations::TRUNCATE).then_some(fs_truncate::<F> as _);
+		fsops.utime = has(Operations::UTIME).then_some(fs_utime::<F> as _);
+		fsops.utimens = has(Operations::UTIME).then_some(fs_utimens::<F> as _);
+		fsops.write = has(Operations::WRITE).then_some(fs_write::<F> as _);
+
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				fsops.fallocate = has(Operations::FALLOCATE).then_some(fs_fallocate::<F> as _);
+				fsops.write_buf = has(Operations::WRITE_BUF).then_some(fs_write_buf::<F> as _);
+			}
+		}
+	}
+}
+
+impl<F: FilesystemNamespace> Register<F> for NamespaceCap<true> {
+	fn register(ops: Operations, table: &mut OperationTable) {
+		let has = |op| ops.contains(op);
+		let fsops = &mut table.0;
+
+		fsops.mknod = has(Operations::MKNOD).then_some(fs_mknod::<F> as _);
+		fsops.mkdir = has(Operations::MKDIR).then_some(fs_mkdir::<F> as _);
+		fsops.unlink = has(Operations::UNLINK).then_some(fs_unlink::<F> as _);
+		fsops.rmdir = has(Operations::RMDIR).then_some(fs_rmdir::<F> as _);
+		fsops.symlink = has(Operations::SYMLINK).then_some(fs_symlink::<F> as _);
+		fsops.rename = has(Operations::RENAME).then_some(fs_rename::<F> as _);
+		fsops.link = has(Operations::LINK).then_some(fs_link::<F> as _);
+		fsops.create = has(Operations::CREATE).then_some(fs_create::<F> as _);
+	}
+}
+
+impl<F: FilesystemXattr> Register<F> for XattrCap<true> {
+	fn register(ops: Operations, table: &mut OperationTable) {
+		let has = |op| ops.contains(op);
+		let fsops = &mut table.0;
+
+		fsops.getxattr = has(Operations::GETXATTR).then_some(fs_getxattr::<F> as _);
+		fsops.setxattr = has(Operations::SETXATTR).then_some(fs_setxattr::<F> as _);
+		fsops.listxattr = has(Operations::LISTXATTR).then_some(fs_listxattr::<F> as _);
+		fsops.removexattr = has(Operations::REMOVEXATTR).then_some(fs_removexattr::<F> as _);
+	}
+}
+
+impl<F: FilesystemLock> Register<F> for LockCap<true> {
+	fn register(ops: Operations, table: &mut OperationTable) {
+		let has = |op| ops.contains(op);
+		let fsops = &mut table.0;
+
+		fsops.lock = has(Operations::LOCK).then_some(fs_lock::<F> as _);
+
+		// not supported by OpenBSD
+		#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+		{
+			fsops.flock = has(Operations::FLOCK).then_some(fs_flock::<F> as _);
+		}
+	}
+}
+
+pub(crate) fn map_mountpoint(mp: &Path) -> Result<CString> {
+	CString::new(mp.as_os_str().as_bytes()).map_err(|_| Error::from_raw_os_error(libc::EINVAL))
+}
+
+/// Unmount whatever filesystem is mounted on `mp`, without tearing down its session.
+pub(crate) fn unmount(mp: &CStr) {
+	cfg_if! {
+		if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+			unsafe { fuse2::fuse_unmount(mp.as_ptr(), std::ptr::null_mut()) };
+		} else {
+			// OpenBSD's fuse_unmount() ignores a mountpoint without a channel
+			unsafe { libc::unmount(mp.as_ptr(), 0) };
+		}
+	}
+}
+
+/// Command-line arguments for libfuse, starting with the program name.
+pub(crate) struct Args {
+	_strings: Vec<CString>,
+	_argv:    Vec<*mut c_char>,
+	pub raw:  fuse2::fuse_args,
+}
+
+impl Args {
+	pub(crate) fn new(opts: impl IntoIterator<Item = CString>) -> Self {
+		let strings = once(c"fuse2rs".into()).chain(opts).collect::<Vec<CString>>();
+		let mut argv = strings
+			.iter()
+			.map(|s| s.as_ptr() as *mut c_char)
+			.chain(once(std::ptr::null_mut()))
+			.collect::<Vec<_>>();
+		let raw = fuse2::fuse_args {
+			argc:      strings.len() as c_int,
+			argv:      argv.as_mut_ptr(),
+			allocated: 0,
+		};
+
+		Self {
+			_strings: strings,
+			_argv: argv,
+			raw,
+		}
+	}
+}
+
+impl Drop for Args {
+	fn drop(&mut self) {
+		// libfuse replaces `raw` with its own copy, if it modifies the arguments
+		unsafe { fuse2::fuse_opt_free_args(&mut self.raw) };
+	}
+}
+
+pub(crate) fn xmount<F>(mp: &Path, fs: F, opts: Vec<CString>) -> Result<F>
+where
+	F: Filesystem + Send,
+	F::Capabilities: CapabilitySet<F>,
+	F::Handle: Send,
+	F::DirHandle: Send,
+{
+	let mp = map_mountpoint(mp)?;
+	let args = Args::new(opts.into_iter().chain(once(mp)));
+	let argc = args.raw.argc;
+	let argv = args.raw.argv;
+	let ops = operations::<F>(fs.operations());
+	let ctx = Context::new(fs);
 
 	let ec;
 	cfg_if! {
 		if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
-			ec = unsafe {
-				fuse2::fuse_main_real(argc, argv, &FSOPS, std::mem::size_of_val(&FSOPS), ctx)
+			// like fuse_main_real(), but PollHandles must be invalidated before the teardown
+			let mut mountpoint = std::ptr::null_mut();
+			let mut multithreaded = 0;
+			let fuse = unsafe {
+				fuse2::fuse_setup(
+					argc,
+					argv,
+					&ops,
+					std::mem::size_of_val(&ops),
+					&mut mountpoint,
+					&mut multithreaded,
+					ctx as *mut c_void,
+				)
+			};
+			ec = if fuse.is_null() {
+				1
+			} else {
+				unsafe {
+					let ec = match multithreaded {
+						0 => fuse2::fuse_loop(fuse),
+						_ => fuse2::fuse_loop_mt(fuse),
+					};
+					Context::lock(ctx).kill();
+					fuse2::fuse_teardown(fuse, mountpoint);
+					ec
+				}
 			};
 		} else {
 			ec = unsafe {
-				fuse2::fuse_main(argc, argv, &FSOPS, ctx)
+				fuse2::fuse_main(argc, argv, &ops, ctx as *mut c_void)
 			};
 		}
 	};
+
+	let ctx = unsafe { Context::from_raw(ctx) };
 	match ec {
-		0 => Ok(()),
+		0 => Ok(ctx.fs),
 		_ => Err(Error::from_raw_os_error(libc::EIO)),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::marker::PhantomData;
+
+	use super::*;
+	use crate::{FileAttr, ReadOnly, ReadWrite};
+
+	/// A filesystem, that implements the capability traits, but only declares `C`.
+	struct Nullfs<C>(PhantomData<C>);
+
+	impl<C> Filesystem for Nullfs<C> {
+		type Capabilities = C;
+		type DirHandle = ();
+		type Handle = ();
+
+		fn getattr(&mut self, _req: &Request, _path: &Path) -> Result<FileAttr> {
+			Err(Error::from_raw_os_error(libc::ENOENT))
+		}
+
+		fn readdir(
+			&mut self,
+			_req: &Request,
+			_path: &Path,
+			_off: u64,
+			_filler: &mut DirFiller,
+			_dh: &mut (),
+			_info: &FileInfo,
+		) -> Result<()> {
+			Ok(())
+		}
+
+		fn read(
+			&mut self,
+			_req: &Request,
+			_path: &Path,
+			_off: u64,
+			_buf: &mut [u8],
+			_fh: &mut (),
+			_info: &FileInfo,
+		) -> Result<usize> {
+			Ok(0)
+		}
+
+		fn open(&mut self, _req: &Request, _path: &Path, _info: &mut FileInfo) -> Result<()> {
+			Ok(())
+		}
+
+		fn opendir(&mut self, _req: &Request, _path: &Path, _info: &mut FileInfo) -> Result<()> {
+			Ok(())
+		}
+	}
+
+	impl<C> FilesystemWrite for Nullfs<C> {}
+
+	impl<C> FilesystemNamespace for Nullfs<C> {}
+
+	#[test]
+	fn operations_empty() {
+		let ops = operations::<Nullfs<ReadWrite>>(Operations::empty());
+		assert!(ops.getattr.is_some());
+		assert!(ops.create.is_none());
+		assert!(ops.statfs.is_none());
+		assert!(ops.readlink.is_none());
+	}
+
+	#[test]
+	fn operations_read_only() {
+		// the operations of undeclared capabilities are never registered
+		let ops = operations::<Nullfs<ReadOnly>>(Operations::all());
+		assert!(ops.readlink.is_some());
+		assert!(ops.write.is_none());
+		assert!(ops.mknod.is_none());
+	}
+
+	#[test]
+	fn operations_default() {
+		let ops = operations::<Nullfs<ReadWrite>>(Operations::default());
+		assert!(ops.create.is_some());
+		assert!(ops.write.is_some());
+	}
+
+	#[test]
+	fn copy_link_truncates() {
+		let mut buf = [0xff; 4];
+		assert_eq!(copy_link(b"abcdef", &mut buf), 3);
+		assert_eq!(&buf, b"abc\0");
+	}
+
+	#[test]
+	fn copy_link_empty_buffer() {
+		assert_eq!(copy_link(b"abc", &mut []), 0);
+	}
+
+	#[test]
+	fn copy_link_dirty_buffer() {
+		let mut buf = [b'x'; 8];
+		assert_eq!(copy_link(b"ab", &mut buf), 2);
+		assert_eq!(&buf[..3], b"ab\0");
+	}
+
+	#[test]
+	#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+	fn fallocate_mode() {
+		use crate::FallocateMode;
+
+		assert_eq!(
+			map_fallocate_mode(0),
+			Some(FallocateMode::Allocate { keep_size: false })
+		);
+		assert_eq!(
+			map_fallocate_mode(0x01),
+			Some(FallocateMode::Allocate { keep_size: true })
+		);
+		assert_eq!(map_fallocate_mode(0x03), Some(FallocateMode::PunchHole));
+		assert_eq!(
+			map_fallocate_mode(0x10),
+			Some(FallocateMode::ZeroRange { keep_size: false })
+		);
+		assert_eq!(
+			map_fallocate_mode(0x11),
+			Some(FallocateMode::ZeroRange { keep_size: true })
+		);
+	}
+
+	#[test]
+	#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+	fn fallocate_mode_unsupported() {
+		// punching a hole must never change the size of the file
+		assert_eq!(map_fallocate_mode(0x02), None);
+		assert_eq!(map_fallocate_mode(0x08), None);
+		assert_eq!(map_fallocate_mode(0x09), None);
+		assert_eq!(map_fallocate_mode(0x12), None);
+	}
+
+	#[test]
+	#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+	fn write_buf_len_skips_copied_data() {
+		// a fuse_bufvec with two buffers, as allocated by libfuse
+		#[repr(C)]
+		struct Bufvec2 {
+			bufv: fuse2::fuse_bufvec,
+			next: fuse2::fuse_buf,
+		}
+
+		let buf = |size| {
+			fuse2::fuse_buf {
+				size,
+				flags: 0,
+				mem: std::ptr::null_mut(),
+				fd: -1,
+				pos: 0,
+			}
+		};
+		let mut bufv = Bufvec2 {
+			bufv: fuse2::fuse_bufvec {
+				count: 2,
+				idx:   0,
+				off:   0,
+				buf:   [buf(10)],
+			},
+			next: buf(20),
+		};
+		let len = |bufv: &mut Bufvec2| {
+			WriteBuf {
+				bufv: std::ptr::addr_of_mut!(*bufv) as *mut c_void,
+			}
+			.len()
+		};
+		assert_eq!(len(&mut bufv), 30);
+
+		// the first buffer was copied, and 3 bytes of the second one
+		bufv.bufv.idx = 1;
+		bufv.bufv.off = 3;
+		assert_eq!(len(&mut bufv), 17);
+	}
+}
@@ -0,0 +1,189 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::{Condvar, Mutex},
+};
+
+/// Serializes operations on the namespace of a path-based filesystem.
+///
+/// Every operation locks all paths it depends on at once, which makes it impossible
+/// for two operations to deadlock each other, regardless of the order of their paths.
+/// While a path is locked, neither it nor any of its parents can be removed or renamed,
+/// and only one operation at a time can add or remove entries of a directory.
+///
+/// A thread must only hold one `PathGuard` of the same `PathLocks` at a time.
+#[derive(Debug, Default)]
+pub struct PathLocks {
+	slots: Mutex<HashMap<PathBuf, Slot>>,
+	cond:  Condvar,
+}
+
+/// Locks held by an operation, which are released when dropped.
+#[must_use]
+#[derive(Debug)]
+pub struct PathGuard<'a> {
+	locks: &'a PathLocks,
+	set:   LockSet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Mode {
+	/// Something below the path is locked.
+	Intent,
+	/// The path must not be removed or renamed.
+	Shared,
+	/// The path is being removed, replaced or renamed.
+	Exclusive,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Want {
+	mode:    Mode,
+	entries: bool,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+	intent:    usize,
+	shared:    usize,
+	exclusive: bool,
+	entries:   bool,
+}
+
+#[derive(Debug, Default)]
+struct LockSet(HashMap<PathBuf, Want>);
+
+impl PathLocks {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Keep `path` from being removed or renamed,
+	/// e.g. for `getattr()`, `open()` or `write()`.
+	pub fn lock(&self, path: &Path) -> PathGuard<'_> {
+		let mut set = LockSet::default();
+		set.add(path, Mode::Shared);
+		self.acquire(set)
+	}
+
+	/// Lock `path` for being created or removed,
+	/// e.g. for `create()`, `mkdir()`, `unlink()` or `rmdir()`.
+	pub fn lock_entry(&self, path: &Path) -> PathGuard<'_> {
+		let mut set = LockSet::default();
+		set.add_entry(path);
+		self.acquire(set)
+	}
+
+	/// Lock `from` for being renamed to `to`, see `FilesystemNamespace::rename()`.
+	///
+	/// This also prevents the parent directories of both paths from being moved around.
+	pub fn lock_rename(&self, from: &Path, to: &Path) -> PathGuard<'_> {
+		let mut set = LockSet::default();
+		set.add_entry(from);
+		set.add_entry(to);
+		self.acquire(set)
+	}
+
+	/// Lock `from` for being hard linked to `to`, see `FilesystemNamespace::link()`.
+	pub fn lock_link(&self, from: &Path, to: &Path) -> PathGuard<'_> {
+		let mut set = LockSet::default();
+		set.add(from, Mode::Shared);
+		set.add_entry(to);
+		self.acquire(set)
+	}
+
+	fn acquire(&self, set: LockSet) -> PathGuard<'_> {
+		let mut slots = self.slots.lock().unwrap();
+		while set
+			.0
+			.iter()
+			.any(|(path, want)| slots.get(path).is_some_and(|slot| slot.conflicts(want)))
+		{
+			slots = self.cond.wait(slots).unwrap();
+		}
+
+		for (path, want) in &set.0 {
+			let slot = slots.entry(path.clone()).or_default();
+			match want.mode {
+				Mode::Intent => slot.intent += 1,
+				Mode::Shared => slot.shared += 1,
+				Mode::Exclusive => slot.exclusive = true,
+			}
+			slot.entries |= want.entries;
+		}
+
+		PathGuard { locks: self, set }
+	}
+
+	fn release(&self, set: &LockSet) {
+		let mut slots = self.slots.lock().unwrap();
+		for (path, want) in &set.0 {
+			let slot = slots.get_mut(path).unwrap();
+			match want.mode {
+				Mode::Intent => slot.intent -= 1,
+				Mode::Shared => slot.shared -= 1,
+				Mode::Exclusive => slot.exclusive = false,
+			}
+			if want.entries {
+				slot.entries = false;
+			}
+			if slot.is_unused() {
+				slots.remove(path);
+			}
+		}
+		drop(slots);
+		self.cond.notify_all();
+	}
+}
+
+impl Drop for PathGuard<'_> {
+	fn drop(&mut self) {
+		self.locks.release(&self.set);
+	}
+}
+
+impl Slot {
+	fn conflicts(&self, want: &Want) -> bool {
+		if want.entries && self.entries {
+			return true;
+		}
+
+		match want.mode {
+			Mode::Intent | Mode::Shared => self.exclusive,
+			Mode::Exclusive => self.exclusive || self.shared > 0 || self.intent > 0,
+		}
+	}
+
+	fn is_unused(&self) -> bool {
+		self.intent == 0 && self.shared == 0 && !self.exclusive && !self.entries
+	}
+}
+
+impl LockSet {
+	fn want(&mut self, path: &Path, mode: Mode) -> &mut Want {
+		let want = self.0.entry(path.to_owned()).or_insert(Want {
+			mode,
+			entries: false,
+		});
+		want.mode = want.mode.max(mode);
+		want
+	}
+
+	fn add(&mut self, path: &Path, mode: Mode) {
+		for parent in path.ancestors().skip(1) {
+			self.want(parent, Mode::Intent);
+		}
+		self.want(path, mode);
+	}
+
+	fn add_entry(&mut self, path: &Path) {
+		match path.parent() {
+			Some(parent) => {
+				self.add(parent, Mode::Shared);
+				self.want(parent, Mode::Shared).entries = true;
+				self.want(path, Mode::Exclusive);
+			}
+			None => self.add(path, Mode::Exclusive),
+		}
+	}
+}
@@ -0,0 +1,366 @@
+use std::{
+	ffi::{c_char, c_void, CStr, CString, OsStr},
+	io::{Error, Result},
+	marker::PhantomData,
+	os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+	panic,
+	path::Path,
+	thread::{self, JoinHandle},
+};
+
+use cfg_if::cfg_if;
+
+use crate::{
+	ll::{self, fuse2, Args, Context, Shared},
+	CapabilitySet,
+	Filesystem,
+	MountOption,
+};
+
+/// A mounted filesystem that is driven by the caller.
+///
+/// Unlike `mount()`, a `Session` never daemonizes and doesn't install any signal handlers.
+/// Requests can either be processed one at a time with `process_one()`,
+/// whenever the channel file descriptor becomes readable,
+/// or by handing control over to libfuse with `run()`.
+///
+/// The filesystem is unmounted when the `Session` is dropped,
+/// or when calling `unmount()`, which also returns the filesystem.
+pub struct Session<F: Filesystem> {
+	fuse: *mut fuse2::fuse,
+	chan: *mut fuse2::fuse_chan,
+	mp:   CString,
+	ctx:  *mut Shared<F>,
+	buf:  Vec<u8>,
+}
+
+// libfuse doesn't care which thread drives a session, as long as it's only one at a time.
+unsafe impl<F> Send for Session<F>
+where
+	F: Filesystem + Send,
+	F::Handle: Send,
+	F::DirHandle: Send,
+{
+}
+
+impl<F: Filesystem> Session<F> {
+	/// Mount `fs` on `mp`.
+	///
+	/// `MountOption::Foreground` is ignored, a session always runs in the foreground.
+	pub fn new(mp: &Path, fs: F, opts: Vec<MountOption>) -> Result<Self>
+	where
+		F::Capabilities: CapabilitySet<F>,
+	{
+		let mp = ll::map_mountpoint(mp)?;
+		let mut args = Args::new(
+			opts
+				.into_iter()
+				.filter(|opt| !matches!(opt, MountOption::Foreground))
+				.map(|opt| opt.into_cstring())
+		);
+
+		let chan = unsafe { fuse2::fuse_mount(mp.as_ptr(), &mut args.raw) };
+		if chan.is_null() {
+			return Err(Error::from_raw_os_error(libc::EIO));
+		}
+
+		let ops = ll::operations::<F>(fs.operations());
+		let ctx = Context::new(fs);
+		let fuse = unsafe {
+			fuse2::fuse_new(
+				chan,
+				&mut args.raw,
+				&ops,
+				std::mem::size_of_val(&ops),
+				ctx as *mut c_void,
+			)
+		};
+		if fuse.is_null() {
+			unsafe {
+				fuse2::fuse_unmount(mp.as_ptr(), chan);
+				drop(Context::from_raw(ctx));
+			}
+			return Err(Error::from_raw_os_error(libc::EIO));
+		}
+
+		let bufsize;
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				bufsize = unsafe { fuse2::fuse_chan_bufsize(chan) };
+			} else {
+				bufsize = 0;
+			}
+		}
+
+		Ok(Self {
+			fuse,
+			chan,
+			mp,
+			ctx,
+			buf: vec![0; bufsize],
+		})
+	}
+
+	/// Process requests on a new thread, until the returned `BackgroundSession` is dropped.
+	pub fn spawn(mut self) -> Result<BackgroundSession<F>>
+	where
+		F: Send + 'static,
+		F::Handle: Send,
+		F::DirHandle: Send,
+	{
+		let mp = self.mp.clone();
+		let chan = self.chan;
+		let thread = thread::Builder::new()
+			.name("fuse2rs".into())
+			// `BackgroundSession::invalidator()` needs the session, even after `run()` failed
+			.spawn(move || (self.run(), self))?;
+
+		Ok(BackgroundSession {
+			mp,
+			chan,
+			thread: Some(thread),
+		})
+	}
+
+	/// Read and dispatch a single request.
+	///
+	/// This blocks until a request is available, unless the channel file descriptor
+	/// was put into non-blocking mode, so it should be called once `fd()` is readable.
+	/// Returns `Ok(false)` once the filesystem has been unmounted.
+	///
+	/// Since the next request is only read after the callback returned,
+	/// `Request::is_interrupted()` never notices an interruption.
+	///
+	/// Not supported on OpenBSD, where libfuse doesn't expose a way to process a single request.
+	pub fn process_one(&mut self) -> Result<bool> {
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				let se = unsafe { fuse2::fuse_get_session(self.fuse) };
+				if unsafe { fuse2::fuse_session_exited(se) } != 0 {
+					return Ok(false);
+				}
+
+				let mut chan = self.chan;
+				let n = unsafe {
+					fuse2::fuse_chan_recv(&mut chan, self.buf.as_mut_ptr() as *mut c_char, self.buf.len())
+				};
+				match n {
+					0 => Ok(false),
+					n if n == -libc::EINTR || n == -libc::EAGAIN || n == -libc::ENOENT => Ok(true),
+					n if n < 0 => Err(Error::from_raw_os_error(-n)),
+					n => {
+						unsafe {
+							fuse2::fuse_session_process(se, self.buf.as_ptr() as *const c_char, n as usize, chan)
+						};
+						Ok(unsafe { fuse2::fuse_session_exited(se) } == 0)
+					}
+				}
+			} else {
+				Err(Error::from_raw_os_error(libc::ENOSYS))
+			}
+		}
+	}
+
+	/// Process requests until the filesystem is unmounted.
+	///
+	/// Requests are received by multiple threads, but only one callback runs at a time.
+	pub fn run(&mut self) -> Result<()>
+	where
+		F: Send,
+		F::Handle: Send,
+		F::DirHandle: Send,
+	{
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				let ec = unsafe { fuse2::fuse_loop_mt(self.fuse) };
+			} else {
+				let ec = unsafe { fuse2::fuse_loop(self.fuse) };
+			}
+		}
+
+		match ec {
+			0 => Ok(()),
+			_ => Err(Error::from_raw_os_error(libc::EIO)),
+		}
+	}
+}
+
+impl<F: Filesystem> Session<F> {
+	pub(crate) fn mountpoint(&self) -> &CStr {
+		&self.mp
+	}
+
+	/// Unmount the filesystem and return it.
+	pub fn unmount(mut self) -> F {
+		let ctx = unsafe { self.teardown() };
+		std::mem::forget(self);
+		ctx.fs
+	}
+
+	/// A handle for invalidating data cached by the kernel.
+	pub fn invalidator(&self) -> Invalidator<'_> {
+		Invalidator::new(self.chan)
+	}
+
+	/// The file descriptor of the FUSE channel.
+	pub fn fd(&self) -> RawFd {
+		unsafe { fuse2::fuse_chan_fd(self.chan) }
+	}
+
+	/// # Safety
+	/// Must only be called once.
+	unsafe fn teardown(&mut self) -> Context<F> {
+		Context::lock(self.ctx).kill();
+		fuse2::fuse_unmount(self.mp.as_ptr(), self.chan);
+		fuse2::fuse_destroy(self.fuse);
+		Context::from_raw(self.ctx)
+	}
+}
+
+impl<F: Filesystem> AsRawFd for Session<F> {
+	fn as_raw_fd(&self) -> RawFd {
+		self.fd()
+	}
+}
+
+impl<F: Filesystem> AsFd for Session<F> {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		unsafe { BorrowedFd::borrow_raw(self.fd()) }
+	}
+}
+
+impl<F: Filesystem> Drop for Session<F> {
+	fn drop(&mut self) {
+		drop(unsafe { self.teardown() });
+	}
+}
+
+/// A session that processes requests on its own thread, see `spawn_mount()`.
+///
+/// The filesystem is unmounted when the `BackgroundSession` is dropped,
+/// or when calling `unmount()`, which also returns the filesystem.
+pub struct BackgroundSession<F: Filesystem> {
+	mp:     CString,
+	chan:   *mut fuse2::fuse_chan,
+	thread: Option<JoinHandle<(Result<()>, Session<F>)>>,
+}
+
+// `chan` is only used by `Invalidator`, which is thread-safe.
+unsafe impl<F> Send for BackgroundSession<F>
+where
+	F: Filesystem + Send,
+	F::Handle: Send,
+	F::DirHandle: Send,
+{
+}
+
+unsafe impl<F: Filesystem> Sync for BackgroundSession<F> {}
+
+impl<F: Filesystem> BackgroundSession<F> {
+	/// A handle for invalidating data cached by the kernel.
+	pub fn invalidator(&self) -> Invalidator<'_> {
+		Invalidator::new(self.chan)
+	}
+
+	/// Unmount the filesystem, wait for its thread to exit and return the filesystem.
+	pub fn unmount(mut self) -> Result<F> {
+		let (r, session) = self.join();
+		let fs = session.unmount();
+		r.map(|()| fs)
+	}
+
+	fn join(&mut self) -> (Result<()>, Session<F>) {
+		ll::unmount(&self.mp);
+		match self.thread.take().unwrap().join() {
+			Ok(r) => r,
+			Err(e) => panic::resume_unwind(e),
+		}
+	}
+}
+
+impl<F: Filesystem> Drop for BackgroundSession<F> {
+	fn drop(&mut self) {
+		if self.thread.is_some() {
+			let _ = self.join();
+		}
+	}
+}
+
+/// Drops attributes, directory entries and data, that the kernel has cached for a mount.
+///
+/// This is needed when files change behind the back of the kernel,
+/// which would otherwise keep serving stale content until its caches time out.
+/// An `Invalidator` can be used from any thread, while requests are being processed.
+///
+/// libfuse 2 doesn't expose the node IDs that the kernel uses for the files below `/`,
+/// so invalidating such a file drops the directory entry of its ancestor in `/` instead.
+/// The kernel then looks up everything below that entry again, which refreshes the attributes,
+/// and the data cached for a file is dropped when it is opened next,
+/// unless `FileInfo::keep_cache` is set.
+///
+/// Not supported on OpenBSD, where invalidating always fails with `ENOSYS`.
+#[derive(Debug, Clone, Copy)]
+pub struct Invalidator<'a> {
+	#[cfg_attr(not(any(target_os = "freebsd", target_os = "linux")), allow(dead_code))]
+	chan:     *mut fuse2::fuse_chan,
+	_session: PhantomData<&'a ()>,
+}
+
+unsafe impl Send for Invalidator<'_> {}
+unsafe impl Sync for Invalidator<'_> {}
+
+impl Invalidator<'_> {
+	fn new(chan: *mut fuse2::fuse_chan) -> Self {
+		Self {
+			chan,
+			_session: PhantomData,
+		}
+	}
+
+	/// Invalidate the cached attributes and data of `path`.
+	pub fn invalidate(&self, path: &Path) -> Result<()> {
+		cfg_if! {
+			if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
+				use std::os::unix::ffi::OsStrExt;
+
+				let top = path.components().find_map(|c| {
+					match c {
+						std::path::Component::Normal(name) => Some(name),
+						_ => None,
+					}
+				});
+				let r = match top {
+					Some(name) => unsafe {
+						fuse2::fuse_lowlevel_notify_inval_entry(
+							self.chan,
+							ROOT_ID,
+							name.as_bytes().as_ptr() as *const c_char,
+							name.len(),
+						)
+					},
+					None => unsafe { fuse2::fuse_lowlevel_notify_inval_inode(self.chan, ROOT_ID, 0, 0) },
+				};
+
+				match r {
+					0 => Ok(()),
+					// the kernel has nothing cached
+					r if r == -libc::ENOENT => Ok(()),
+					r => Err(Error::from_raw_os_error(-r)),
+				}
+			} else {
+				let _ = path;
+				Err(Error::from_raw_os_error(libc::ENOSYS))
+			}
+		}
+	}
+
+	/// Invalidate the cached directory entry `name` inside of `parent`,
+	/// along with the attributes and data cached for it.
+	pub fn invalidate_entry(&self, parent: &Path, name: &OsStr) -> Result<()> {
+		self.invalidate(&parent.join(name))
+	}
+}
+
+/// The node ID of `/`, which is the only one that is fixed in libfuse 2.
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+const ROOT_ID: fuse2::fuse_ino_t = 1;
@@ -0,0 +1,376 @@
+use std::{
+	collections::BTreeMap,
+	ffi::{CString, OsString},
+	io::{Error, Result},
+	os::unix::ffi::OsStrExt,
+	path::{Component, Path, PathBuf},
+	sync::Arc,
+	time::SystemTime,
+};
+
+use crate::{
+	Caps,
+	Clock,
+	DirEntry,
+	DirFiller,
+	DirSnapshot,
+	FileAttr,
+	FileInfo,
+	FileType,
+	Filesystem,
+	FilesystemWrite,
+	Request,
+	SystemClock,
+};
+
+type ReadFn = Box<dyn FnMut() -> Result<Vec<u8>> + Send>;
+type WriteFn = Box<dyn FnMut(&[u8]) -> Result<()> + Send>;
+
+/// A read-mostly filesystem of synthetic files, like procfs.
+///
+/// Files are added with `insert()` and are either static, generated by a closure
+/// when they are opened, or control files, that pass everything written to them to a closure.
+/// Missing parent directories are created automatically.
+pub struct VirtualTree {
+	root:  Entry,
+	next:  u64,
+	uid:   u32,
+	gid:   u32,
+	clock: Arc<dyn Clock>,
+}
+
+/// A node of a `VirtualTree`, see `VirtualTree::insert()`.
+pub struct VirtualNode {
+	kind:  Kind,
+	perm:  Option<u16>,
+	write: Option<WriteFn>,
+}
+
+struct Entry {
+	ino:   u64,
+	perm:  u16,
+	mtime: SystemTime,
+	kind:  Kind,
+	write: Option<WriteFn>,
+}
+
+enum Kind {
+	Dir(BTreeMap<OsString, Entry>),
+	File(Arc<[u8]>),
+	Generated(ReadFn),
+	Symlink(PathBuf),
+}
+
+impl VirtualNode {
+	/// An empty directory.
+	pub fn dir() -> Self {
+		Self::new(Kind::Dir(BTreeMap::new()))
+	}
+
+	/// A file with static contents.
+	pub fn file(contents: impl Into<Vec<u8>>) -> Self {
+		Self::new(Kind::File(contents.into().into()))
+	}
+
+	/// A file, whose contents are generated by `f` every time it is opened.
+	///
+	/// Since its size is unknown beforehand, it is reported as empty
+	/// and the file is opened with `FileInfo::direct_io`.
+	pub fn generated(f: impl FnMut() -> Result<Vec<u8>> + Send + 'static) -> Self {
+		Self::new(Kind::Generated(Box::new(f)))
+	}
+
+	/// A write-only file, which calls `f` with the data of every write.
+	pub fn control(f: impl FnMut(&[u8]) -> Result<()> + Send + 'static) -> Self {
+		Self::file(Vec::new()).on_write(f)
+	}
+
+	/// A symbolic link to `target`.
+	pub fn symlink(target: impl Into<PathBuf>) -> Self {
+		Self::new(Kind::Symlink(target.into()))
+	}
+
+	/// Make a file writable, by calling `f` with the data of every write.
+	///
+	/// The offset of a write is ignored, and truncating the file does nothing.
+	pub fn on_write(mut self, f: impl FnMut(&[u8]) -> Result<()> + Send + 'static) -> Self {
+		self.write = Some(Box::new(f));
+		self
+	}
+
+	/// Override the default permissions,
+	/// which are `0o555` for directories, `0o444` or `0o644` for files and `0o200` for control files.
+	pub fn perm(mut self, perm: u16) -> Self {
+		self.perm = Some(perm);
+		self
+	}
+
+	fn new(kind: Kind) -> Self {
+		Self {
+			kind,
+			perm: None,
+			write: None,
+		}
+	}
+
+	fn default_perm(&self) -> u16 {
+		match (&self.kind, &self.write) {
+			(Kind::Dir(_), _) => 0o555,
+			(Kind::Symlink(_), _) => 0o777,
+			(Kind::File(data), Some(_)) if data.is_empty() => 0o200,
+			(_, Some(_)) => 0o644,
+			(_, None) => 0o444,
+		}
+	}
+}
+
+impl VirtualTree {
+	pub fn new() -> Self {
+		Self::with_clock(Arc::new(SystemClock))
+	}
+
+	/// A tree, whose timestamps come from `clock`, see `Filesystem::clock()`.
+	pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+		Self {
+			root: Entry {
+				ino:   1,
+				perm:  0o555,
+				mtime: clock.now(),
+				kind:  Kind::Dir(BTreeMap::new()),
+				write: None,
+			},
+			next: 2,
+			uid: unsafe { libc::getuid() },
+			gid: unsafe { libc::getgid() },
+			clock,
+		}
+	}
+
+	/// Add `node` at `path`, replacing whatever was there before.
+	///
+	/// # Panics
+	/// If `path` is `/`, or one of its parents isn't a directory.
+	pub fn insert(&mut self, path: impl AsRef<Path>, node: VirtualNode) -> &mut Self {
+		let path = path.as_ref();
+		let name = path
+			.file_name()
+			.unwrap_or_else(|| panic!("invalid path: {}", path.display()));
+		let mtime = self.root.mtime;
+
+		let mut dir = &mut self.root;
+		for c in path.parent().unwrap().components() {
+			let Component::Normal(c) = c else {
+				continue;
+			};
+			let Kind::Dir(entries) = &mut dir.kind else {
+				panic!("not a directory: {}", path.display());
+			};
+			dir = entries.entry(c.to_owned()).or_insert_with(|| {
+				let ino = self.next;
+				self.next += 1;
+				Entry {
+					ino,
+					perm: 0o555,
+					mtime,
+					kind: Kind::Dir(BTreeMap::new()),
+					write: None,
+				}
+			});
+		}
+
+		let Kind::Dir(entries) = &mut dir.kind else {
+			panic!("not a directory: {}", path.display());
+		};
+		let entry = Entry {
+			ino: self.next,
+			perm: node.perm.unwrap_or_else(|| node.default_perm()),
+			mtime,
+			kind: node.kind,
+			write: node.write,
+		};
+		self.next += 1;
+		entries.insert(name.to_owned(), entry);
+		self
+	}
+
+	fn lookup(&mut self, path: &Path) -> Result<&mut Entry> {
+		let mut entry = &mut self.root;
+		for c in path.components() {
+			let Component::Normal(c) = c else {
+				continue;
+			};
+			entry = match &mut entry.kind {
+				Kind::Dir(entries) => entries.get_mut(c),
+				_ => return Err(Error::from_raw_os_error(libc::ENOTDIR)),
+			}
+			.ok_or_else(|| Error::from_raw_os_error(libc::ENOENT))?;
+		}
+		Ok(entry)
+	}
+}
+
+impl Entry {
+	fn kind(&self) -> FileType {
+		match self.kind {
+			Kind::Dir(_) => FileType::Directory,
+			Kind::File(_) | Kind::Generated(_) => FileType::RegularFile,
+			Kind::Symlink(_) => FileType::Symlink,
+		}
+	}
+}
+
+impl Filesystem for VirtualTree {
+	type Capabilities = Caps<true, false, false, false>;
+	type DirHandle = DirSnapshot;
+	type Handle = Arc<[u8]>;
+
+	fn clock(&self) -> Arc<dyn Clock> {
+		self.clock.clone()
+	}
+
+	fn getattr(&mut self, _req: &Request, path: &Path) -> Result<FileAttr> {
+		let (uid, gid) = (self.uid, self.gid);
+		let entry = self.lookup(path)?;
+		let (size, nlink) = match &entry.kind {
+			Kind::Dir(entries) => {
+				let subdirs = entries
+					.values()
+					.filter(|e| matches!(e.kind, Kind::Dir(_)))
+					.count();
+				(0, 2 + subdirs as u32)
+			}
+			Kind::File(data) => (data.len() as u64, 1),
+			Kind::Generated(_) => (0, 1),
+			Kind::Symlink(target) => (target.as_os_str().len() as u64, 1),
+		};
+
+		Ok(FileAttr {
+			ino: entry.ino,
+			size,
+			atime: entry.mtime,
+			mtime: entry.mtime,
+			ctime: entry.mtime,
+			btime: entry.mtime,
+			kind: entry.kind(),
+			perm: entry.perm,
+			uid,
+			gid,
+			nlink,
+			..FileAttr::default()
+		})
+	}
+
+	fn readdir(
+		&mut self,
+		_req: &Request,
+		_path: &Path,
+		off: u64,
+		filler: &mut DirFiller,
+		dh: &mut DirSnapshot,
+		_info: &FileInfo,
+	) -> Result<()> {
+		dh.fill(off, filler);
+		Ok(())
+	}
+
+	fn read(
+		&mut self,
+		_req: &Request,
+		_path: &Path,
+		off: u64,
+		buf: &mut [u8],
+		fh: &mut Arc<[u8]>,
+		_info: &FileInfo,
+	) -> Result<usize> {
+		let off = usize::try_from(off).unwrap_or(usize::MAX).min(fh.len());
+		let len = buf.len().min(fh.len() - off);
+		buf[..len].copy_from_slice(&fh[off..(off + len)]);
+		Ok(len)
+	}
+
+	fn open(&mut self, _req: &Request, path: &Path, info: &mut FileInfo) -> Result<Arc<[u8]>> {
+		let entry = self.lookup(path)?;
+		if info.flags.is_writable() && entry.write.is_none() {
+			return Err(Error::from_raw_os_error(libc::EACCES));
+		}
+
+		match &mut entry.kind {
+			Kind::Dir(_) => Err(Error::from_raw_os_error(libc::EISDIR)),
+			Kind::File(data) => Ok(data.clone()),
+			// don't generate the contents, if they can't be read anyway
+			Kind::Generated(_) if !info.flags.is_readable() => Ok(Arc::from([])),
+			Kind::Generated(f) => {
+				info.direct_io = true;
+				f().map(Arc::from)
+			}
+			Kind::Symlink(_) => Err(Error::from_raw_os_error(libc::ELOOP)),
+		}
+	}
+
+	fn opendir(
+		&mut self,
+		_req: &Request,
+		path: &Path,
+		_info: &mut FileInfo,
+	) -> Result<DirSnapshot> {
+		let Kind::Dir(entries) = &self.lookup(path)?.kind else {
+			return Err(Error::from_raw_os_error(libc::ENOTDIR));
+		};
+
+		let dots = [c".", c".."].map(|name| {
+			DirEntry {
+				name: name.to_owned(),
+				kind: FileType::Directory,
+			}
+		});
+		let entries = entries.iter().map(|(name, e)| {
+			DirEntry {
+				// file names never contain a NUL byte
+				name: CString::new(name.as_bytes()).unwrap(),
+				kind: e.kind(),
+			}
+		});
+		Ok(dots.into_iter().chain(entries).collect())
+	}
+
+	fn readlink(&mut self, _req: &Request, path: &Path) -> Result<PathBuf> {
+		match &self.lookup(path)?.kind {
+			Kind::Symlink(target) => Ok(target.clone()),
+			_ => Err(Error::from_raw_os_error(libc::EINVAL)),
+		}
+	}
+}
+
+impl FilesystemWrite for VirtualTree {
+	fn write(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		_off: u64,
+		buf: &[u8],
+		_fh: &mut Arc<[u8]>,
+		_info: &FileInfo,
+	) -> Result<usize> {
+		let entry = self.lookup(path)?;
+		let f = entry
+			.write
+			.as_mut()
+			.ok_or_else(|| Error::from_raw_os_error(libc::EACCES))?;
+		f(buf)?;
+		entry.mtime = req.now();
+		Ok(buf.len())
+	}
+
+	fn truncate(&mut self, _req: &Request, path: &Path, _size: u64) -> Result<()> {
+		match self.lookup(path)?.write {
+			Some(_) => Ok(()),
+			None => Err(Error::from_raw_os_error(libc::EACCES)),
+		}
+	}
+}
+
+impl Default for VirtualTree {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -3,4 +3,7 @@
 # define FUSE_USE_VERSION 26
 #endif
 #include <fuse.h>
+#if defined(__FreeBSD__) || defined(__linux__)
+# include <fuse_lowlevel.h>
+#endif
 
@@ -0,0 +1,46 @@
+use std::{
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	task::{Wake, Waker},
+	thread,
+};
+
+use fuse2rs::{CancellationToken, Request};
+
+struct Counter(AtomicUsize);
+
+impl Wake for Counter {
+	fn wake(self: Arc<Self>) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn cancel() {
+	let token = CancellationToken::new();
+	assert!(token.check().is_ok());
+
+	let counter = Arc::new(Counter(AtomicUsize::new(0)));
+	let waker = Waker::from(counter.clone());
+	token.register(&waker);
+	token.register(&waker);
+
+	let clone = token.clone();
+	thread::spawn(move || clone.cancel()).join().unwrap();
+	assert!(token.is_cancelled());
+	assert_eq!(token.check().unwrap_err().raw_os_error(), Some(libc::EINTR));
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+	// wakers registered after cancelling are woken right away
+	token.register(&waker);
+	assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn request_is_sync() {
+	// a request can be shared with scoped threads, that watch its token
+	fn sync<T: Sync>(_: &T) {}
+	sync(&Request::new(0, 0, 0o022));
+}
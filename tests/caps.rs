@@ -0,0 +1,191 @@
+use std::{
+	io::{Error, Result},
+	path::Path,
+};
+
+use fuse2rs::*;
+
+struct Rofs;
+
+impl Filesystem for Rofs {
+	type Capabilities = ReadOnly;
+	type Handle = ();
+	type DirHandle = ();
+
+	fn getattr(&mut self, _req: &Request, _path: &Path) -> Result<FileAttr> {
+		Ok(FileAttr::default())
+	}
+
+	fn readdir(
+		&mut self,
+		_req: &Request,
+		_path: &Path,
+		_off: u64,
+		_filler: &mut DirFiller,
+		_dh: &mut (),
+		_info: &FileInfo,
+	) -> Result<()> {
+		Ok(())
+	}
+
+	fn read(
+		&mut self,
+		_req: &Request,
+		_path: &Path,
+		_off: u64,
+		_buf: &mut [u8],
+		_fh: &mut (),
+		_info: &FileInfo,
+	) -> Result<usize> {
+		Ok(0)
+	}
+
+	fn open(&mut self, _req: &Request, _path: &Path, _info: &mut FileInfo) -> Result<()> {
+		Ok(())
+	}
+
+	fn opendir(&mut self, _req: &Request, _path: &Path, _info: &mut FileInfo) -> Result<()> {
+		Ok(())
+	}
+}
+
+struct Rwfs(Rofs);
+
+impl Filesystem for Rwfs {
+	type Capabilities = ReadWrite;
+	type Handle = ();
+	type DirHandle = ();
+
+	fn getattr(&mut self, req: &Request, path: &Path) -> Result<FileAttr> {
+		self.0.getattr(req, path)
+	}
+
+	fn readdir(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		filler: &mut DirFiller,
+		dh: &mut Self::DirHandle,
+		info: &FileInfo,
+	) -> Result<()> {
+		self.0.readdir(req, path, off, filler, dh, info)
+	}
+
+	fn read(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &mut [u8],
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<usize> {
+		self.0.read(req, path, off, buf, fh, info)
+	}
+
+	fn open(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Self::Handle> {
+		self.0.open(req, path, info)
+	}
+
+	fn opendir(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Self::DirHandle> {
+		self.0.opendir(req, path, info)
+	}
+}
+
+impl FilesystemWrite for Rwfs {
+	fn write(
+		&mut self,
+		_req: &Request,
+		_path: &Path,
+		_off: u64,
+		_buf: &[u8],
+		_fh: &mut (),
+		_info: &FileInfo,
+	) -> Result<usize> {
+		Err(Error::from_raw_os_error(libc::ENOSPC))
+	}
+}
+
+impl FilesystemNamespace for Rwfs {}
+
+/// Refuses to remove anything, but otherwise forwards to the inner filesystem.
+struct NoUnlink<F>(F);
+
+impl<F: Filesystem> Filesystem for NoUnlink<F> {
+	type Capabilities = F::Capabilities;
+	type Handle = F::Handle;
+	type DirHandle = F::DirHandle;
+
+	fn getattr(&mut self, req: &Request, path: &Path) -> Result<FileAttr> {
+		self.0.getattr(req, path)
+	}
+
+	fn readdir(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		filler: &mut DirFiller,
+		dh: &mut Self::DirHandle,
+		info: &FileInfo,
+	) -> Result<()> {
+		self.0.readdir(req, path, off, filler, dh, info)
+	}
+
+	fn read(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &mut [u8],
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<usize> {
+		self.0.read(req, path, off, buf, fh, info)
+	}
+
+	fn open(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Self::Handle> {
+		self.0.open(req, path, info)
+	}
+
+	fn opendir(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<Self::DirHandle> {
+		self.0.opendir(req, path, info)
+	}
+}
+
+impl<F: FilesystemWrite> FilesystemWrite for NoUnlink<F> {
+	fn write(
+		&mut self,
+		req: &Request,
+		path: &Path,
+		off: u64,
+		buf: &[u8],
+		fh: &mut Self::Handle,
+		info: &FileInfo,
+	) -> Result<usize> {
+		self.0.write(req, path, off, buf, fh, info)
+	}
+}
+
+impl<F: FilesystemNamespace> FilesystemNamespace for NoUnlink<F> {
+	fn unlink(&mut self, _req: &Request, _path: &Path) -> Result<()> {
+		Err(Error::from_raw_os_error(libc::EPERM))
+	}
+}
+
+fn mountable<F>()
+where
+	F: Filesystem,
+	F::Capabilities: CapabilitySet<F>,
+{
+}
+
+#[test]
+fn wrappers() {
+	mountable::<Rofs>();
+	mountable::<Rwfs>();
+	mountable::<NoUnlink<Rofs>>();
+	mountable::<NoUnlink<Rwfs>>();
+	mountable::<Box<dyn Filesystem<Capabilities = ReadOnly, Handle = (), DirHandle = ()>>>();
+}
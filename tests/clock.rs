@@ -0,0 +1,25 @@
+use std::{
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use fuse2rs::{ManualClock, Request};
+
+#[test]
+fn manual_clock() {
+	let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+	let clock = Arc::new(ManualClock::new(start));
+	let req = Request::new(0, 0, 0o022).with_clock(clock.clone());
+	assert_eq!(req.now(), start);
+	assert_eq!(req.now(), start);
+
+	clock.advance(Duration::from_secs(5));
+	assert_eq!(req.now(), start + Duration::from_secs(5));
+
+	clock.set(SystemTime::UNIX_EPOCH);
+	assert_eq!(req.now(), SystemTime::UNIX_EPOCH);
+
+	// there is no libfuse request behind it
+	assert!(!req.is_interrupted());
+	assert!(!req.cancellation_token().is_cancelled());
+}
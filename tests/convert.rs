@@ -0,0 +1,50 @@
+use std::{
+	fs,
+	os::unix::fs::PermissionsExt,
+	time::{Duration, SystemTime},
+};
+
+use fuse2rs::{FileAttr, FileType};
+use tempfile::tempdir;
+
+#[test]
+fn metadata() {
+	let dir = tempdir().unwrap();
+	let path = dir.path().join("file");
+	fs::write(&path, b"Hello World\n").unwrap();
+	fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+	let attr = FileAttr::from(fs::metadata(&path).unwrap());
+	assert!(matches!(attr.kind, FileType::RegularFile));
+	assert_eq!(attr.size, 12);
+	assert_eq!(attr.perm, 0o640);
+	assert_eq!(attr.nlink, 1);
+
+	let attr = FileAttr::from(fs::metadata(dir.path()).unwrap());
+	assert!(matches!(attr.kind, FileType::Directory));
+}
+
+#[test]
+fn stat() {
+	let attr = FileAttr {
+		ino: 42,
+		size: 1234,
+		kind: FileType::Symlink,
+		perm: 0o777,
+		uid: 1000,
+		gid: 100,
+		mtime: SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123),
+		atime: SystemTime::UNIX_EPOCH - Duration::new(10, 250_000_000),
+		..FileAttr::default()
+	};
+
+	let attr2 = FileAttr::from(libc::stat::from(&attr));
+	assert_eq!(attr2.ino, attr.ino);
+	assert_eq!(attr2.size, attr.size);
+	assert!(matches!(attr2.kind, FileType::Symlink));
+	assert_eq!(attr2.perm, attr.perm);
+	assert_eq!(attr2.uid, attr.uid);
+	assert_eq!(attr2.gid, attr.gid);
+	assert_eq!(attr2.mtime, attr.mtime);
+	assert_eq!(attr2.atime, attr.atime);
+}
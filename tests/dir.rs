@@ -0,0 +1,22 @@
+use std::fs;
+
+use fuse2rs::{DirSnapshot, FileType};
+use tempfile::tempdir;
+
+#[test]
+fn snapshot() {
+	let dir = tempdir().unwrap();
+	fs::write(dir.path().join("file"), b"").unwrap();
+	fs::create_dir(dir.path().join("dir")).unwrap();
+
+	let snap = DirSnapshot::read_dir(dir.path()).unwrap();
+	fs::write(dir.path().join("new"), b"").unwrap();
+
+	let mut entries = snap
+		.entries()
+		.iter()
+		.map(|e| (e.name.to_str().unwrap(), e.kind))
+		.collect::<Vec<_>>();
+	entries.sort_by_key(|(name, _)| *name);
+	assert_eq!(entries, [("dir", FileType::Directory), ("file", FileType::RegularFile)]);
+}
@@ -0,0 +1,23 @@
+use fuse2rs::{AccessMode, OpenFlags};
+
+#[test]
+fn open_flags() {
+	let flags = OpenFlags::from_bits(libc::O_WRONLY | libc::O_APPEND | libc::O_CREAT);
+	assert_eq!(flags.access_mode(), AccessMode::WriteOnly);
+	assert!(!flags.is_readable());
+	assert!(flags.is_writable());
+	assert!(flags.append());
+	assert!(flags.create());
+	assert!(!flags.exclusive());
+	assert!(!flags.truncate());
+
+	let flags = OpenFlags::from_bits(libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW);
+	assert_eq!(flags.access_mode(), AccessMode::ReadOnly);
+	assert!(flags.is_readable());
+	assert!(!flags.is_writable());
+	assert!(flags.directory());
+	assert!(flags.nofollow());
+	assert!(!flags.append());
+
+	assert_eq!(OpenFlags::from_bits(libc::O_RDWR).access_mode(), AccessMode::ReadWrite);
+}
@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use fuse2rs::InodeTable;
+
+#[test]
+fn rename_and_link() {
+	let mut table = InodeTable::new();
+	assert_eq!(table.ino(Path::new("/")), 1);
+
+	let dir = table.ino(Path::new("/dir"));
+	let file = table.ino(Path::new("/dir/file"));
+	assert_ne!(dir, file);
+	assert_eq!(table.ino(Path::new("/dir/file")), file);
+
+	table.rename(Path::new("/dir"), Path::new("/moved"));
+	assert_eq!(table.get(Path::new("/dir")), None);
+	assert_eq!(table.get(Path::new("/dir/file")), None);
+	assert_eq!(table.get(Path::new("/moved")), Some(dir));
+	assert_eq!(table.get(Path::new("/moved/file")), Some(file));
+
+	assert_eq!(table.link(Path::new("/moved/file"), Path::new("/link")), file);
+	table.remove(Path::new("/moved/file"));
+	assert_eq!(table.get(Path::new("/link")), Some(file));
+
+	// numbers are never reused
+	let new = table.ino(Path::new("/moved/file"));
+	assert!(new > file);
+}
+
+#[test]
+fn save_and_load() {
+	let mut table = InodeTable::new();
+	let a = table.ino(Path::new("/a"));
+	let b = table.ino(Path::new("/a/b\nc"));
+
+	let mut buf = Vec::new();
+	table.save(&mut buf).unwrap();
+	let mut table = InodeTable::load(buf.as_slice()).unwrap();
+	assert_eq!(table.get(Path::new("/a")), Some(a));
+	assert_eq!(table.get(Path::new("/a/b\nc")), Some(b));
+	assert!(table.ino(Path::new("/c")) > b);
+
+	assert!(InodeTable::load(&buf[..buf.len() - 1]).is_err());
+	assert!(InodeTable::load(&b"garbage"[..]).is_err());
+}
@@ -0,0 +1,34 @@
+use cfg_if::cfg_if;
+use fuse2rs::ioctl;
+
+#[test]
+fn encode() {
+	cfg_if! {
+		if #[cfg(target_os = "linux")] {
+			// FS_IOC_GETFLAGS
+			assert_eq!(ioctl::ior(b'f', 1, 8), 0x8008_6601);
+			// FS_IOC_SETFLAGS
+			assert_eq!(ioctl::iow(b'f', 2, 8), 0x4008_6602);
+		} else {
+			// FIONREAD
+			assert_eq!(ioctl::ior(b'f', 127, 4), 0x4004_667f);
+			// FIOASYNC
+			assert_eq!(ioctl::iow(b'f', 125, 4), 0x8004_667d);
+		}
+	}
+}
+
+#[test]
+fn decode() {
+	let cmd = ioctl::iowr(b'x', 42, 24);
+	assert!(ioctl::is_read(cmd));
+	assert!(ioctl::is_write(cmd));
+	assert_eq!(ioctl::size(cmd), 24);
+	assert_eq!(ioctl::group(cmd), b'x');
+	assert_eq!(ioctl::num(cmd), 42);
+
+	let cmd = ioctl::io(b'x', 1);
+	assert!(!ioctl::is_read(cmd));
+	assert!(!ioctl::is_write(cmd));
+	assert_eq!(ioctl::size(cmd), 0);
+}
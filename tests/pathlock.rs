@@ -0,0 +1,48 @@
+use std::{
+	path::Path,
+	sync::{mpsc, Arc},
+	thread,
+	time::Duration,
+};
+
+use fuse2rs::PathLocks;
+
+#[test]
+fn rmdir_waits_for_children() {
+	let locks = Arc::new(PathLocks::new());
+	let guard = locks.lock(Path::new("/a/b/c"));
+
+	let (tx, rx) = mpsc::channel();
+	let thread = {
+		let locks = locks.clone();
+		thread::spawn(move || {
+			let _guard = locks.lock_entry(Path::new("/a/b"));
+			tx.send(()).unwrap();
+		})
+	};
+
+	// unrelated entries of the same parents aren't blocked
+	drop(locks.lock_entry(Path::new("/a/d")));
+
+	assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+	drop(guard);
+	rx.recv().unwrap();
+	thread.join().unwrap();
+}
+
+#[test]
+fn crossed_renames() {
+	let locks = Arc::new(PathLocks::new());
+	let threads = [("/a/x", "/b/y"), ("/b/z", "/a/w")].map(|(from, to)| {
+		let locks = locks.clone();
+		thread::spawn(move || {
+			for _ in 0..1000 {
+				let _guard = locks.lock_rename(Path::new(from), Path::new(to));
+			}
+		})
+	});
+
+	for thread in threads {
+		thread.join().unwrap();
+	}
+}
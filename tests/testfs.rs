@@ -1,6 +1,9 @@
 use std::{
 	fmt::{self, Display, Formatter},
-	process::{Child, Command},
+	fs,
+	io::{BufRead, BufReader, Read, Write},
+	path::Path,
+	process::{Child, ChildStdin, ChildStdout, Command, Stdio},
 	thread::sleep,
 	time::{Duration, Instant},
 };
@@ -8,9 +11,12 @@ use std::{
 use cfg_if::cfg_if;
 use tempfile::{tempdir, TempDir};
 
+/// An example, that runs as root and mounts its filesystems on temporary directories.
 struct Harness {
-	dir:   TempDir,
-	child: Child,
+	dirs:   Vec<TempDir>,
+	child:  Child,
+	stdin:  Option<ChildStdin>,
+	stdout: BufReader<ChildStdout>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -39,32 +45,89 @@ where
 		sleep(Duration::from_millis(50));
 	}
 }
+
+fn is_fuse(path: &Path) -> bool {
+	let s = nix::sys::statfs::statfs(path).unwrap();
+
+	cfg_if! {
+		if #[cfg(target_os = "openbsd")] {
+			s.filesystem_type_name() == "fuse"
+		} else if #[cfg(target_os = "freebsd")] {
+			s.filesystem_type_name() == "fusefs"
+		} else if #[cfg(target_os = "linux")] {
+			s.filesystem_type() == nix::sys::statfs::FUSE_SUPER_MAGIC
+		}
+	}
+}
+
+fn example(name: &str) -> Command {
+	let mut cmd = Command::new("doas");
+	cmd.arg(format!("target/debug/examples/{name}"));
+	cmd
+}
+
 impl Harness {
 	fn new() -> Self {
-		let dir = tempdir().unwrap();
+		Self::spawn("testfs", 1)
+	}
 
-		let child = Command::new("doas")
-			.arg("target/debug/examples/testfs")
-			.arg(dir.path())
+	/// Run the example `name` with `n` mountpoints, and wait until all of them are mounted.
+	fn spawn(name: &str, n: usize) -> Self {
+		let dirs = (0..n).map(|_| tempdir().unwrap()).collect::<Vec<_>>();
+
+		let mut child = example(name)
+			.args(dirs.iter().map(|d| d.path()))
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
 			.spawn()
 			.unwrap();
 
 		waitfor(Duration::from_secs(5), || {
-			let s = nix::sys::statfs::statfs(dir.path()).unwrap();
-
-			cfg_if! {
-				if #[cfg(target_os = "openbsd")] {
-					s.filesystem_type_name() == "fuse"
-				} else if #[cfg(target_os = "freebsd")] {
-					s.filesystem_type_name() == "fusefs"
-				} else if #[cfg(target_os = "linux")] {
-										s.filesystem_type() == nix::sys::statfs::FUSE_SUPER_MAGIC
-								}
-			}
+			dirs.iter().all(|d| is_fuse(d.path()))
 		})
 		.unwrap();
 
-		Self { dir, child }
+		Self {
+			dirs,
+			stdin: child.stdin.take(),
+			stdout: BufReader::new(child.stdout.take().unwrap()),
+			child,
+		}
+	}
+
+	fn path(&self, i: usize) -> &Path {
+		self.dirs[i].path()
+	}
+
+	fn write_line(&mut self, line: &str) {
+		writeln!(self.stdin.as_mut().unwrap(), "{line}").unwrap();
+	}
+
+	fn read_line(&mut self) -> String {
+		let mut line = String::new();
+		self.stdout.read_line(&mut line).unwrap();
+		line
+	}
+
+	fn unmount(&self, i: usize) {
+		let status = Command::new("doas")
+			.arg("umount")
+			.arg(self.path(i))
+			.status()
+			.unwrap();
+		assert!(status.success());
+	}
+
+	/// Close stdin and wait for the example to exit successfully, after unmounting everything.
+	///
+	/// Returns the rest of its output.
+	fn wait(mut self) -> String {
+		drop(self.stdin.take());
+		let mut output = String::new();
+		self.stdout.read_to_string(&mut output).unwrap();
+		assert!(self.child.wait().unwrap().success());
+		assert!(self.dirs.iter().all(|d| !is_fuse(d.path())));
+		output
 	}
 }
 
@@ -73,3 +136,76 @@ fn testfs() {
 	let h = Harness::new();
 	drop(h);
 }
+
+#[test]
+fn multifs() {
+	let mut h = Harness::spawn("multifs", 3);
+
+	for i in 0..3 {
+		let text = fs::read_to_string(h.path(i).join("index")).unwrap();
+		assert_eq!(text, format!("{i}\n"));
+	}
+
+	// unmount the second filesystem, without affecting the others
+	h.write_line("1");
+	waitfor(Duration::from_secs(5), || !is_fuse(h.path(1))).unwrap();
+	assert!(is_fuse(h.path(0)));
+	assert!(is_fuse(h.path(2)));
+	assert_eq!(fs::read_to_string(h.path(2).join("index")).unwrap(), "2\n");
+
+	h.wait();
+}
+
+#[test]
+fn syncfs() {
+	let mut h = Harness::spawn("syncfs", 1);
+	let file = h.path(0).join("dir/file");
+	assert_eq!(fs::read_to_string(&file).unwrap(), "0\n");
+
+	// the cached size of the file is stale, unless it was invalidated
+	h.write_line("changed");
+	assert_eq!(h.read_line(), "ok\n");
+	assert_eq!(fs::read_to_string(&file).unwrap(), "changed\n");
+
+	h.wait();
+}
+
+#[test]
+fn pollfs() {
+	let h = Harness::spawn("pollfs", 1);
+	let text = fs::read_to_string(h.path(0).join("test")).unwrap();
+	assert_eq!(text, "Hello World\n");
+
+	// process_one() returns Ok(false) once the filesystem is unmounted
+	h.unmount(0);
+	assert_eq!(h.wait(), "unmounted\n");
+}
+
+#[test]
+fn scopefs() {
+	let dir = tempdir().unwrap();
+	let output = example("scopefs").arg(dir.path()).output().unwrap();
+
+	// the file was read while mounted, and the filesystem is gone once scope() returns
+	assert!(output.status.success());
+	assert_eq!(output.stdout, b"borrowed\n");
+	assert!(!is_fuse(dir.path()));
+}
+
+#[test]
+fn intrfs() {
+	let mut h = Harness::spawn("intrfs", 1);
+	let mut cat = Command::new("cat")
+		.arg(h.path(0).join("block"))
+		.spawn()
+		.unwrap();
+	assert_eq!(h.read_line(), "reading\n");
+
+	// another thread of libfuse receives the interrupt, while the read is blocked
+	cat.kill().unwrap();
+	assert!(!cat.wait().unwrap().success());
+	assert_eq!(h.read_line(), "interrupted\n");
+
+	h.unmount(0);
+	h.wait();
+}
@@ -0,0 +1,147 @@
+use std::{
+	path::Path,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
+
+use fuse2rs::*;
+
+fn read_all(tree: &mut VirtualTree, req: &Request, path: &str) -> Vec<u8> {
+	let path = Path::new(path);
+	let mut info = FileInfo::new(OpenFlags::from_bits(libc::O_RDONLY));
+	let mut fh = tree.open(req, path, &mut info).unwrap();
+	let mut buf = [0; 64];
+	let n = tree.read(req, path, 0, &mut buf, &mut fh, &info).unwrap();
+	buf[..n].to_vec()
+}
+
+#[test]
+fn virtual_tree() {
+	let value = Arc::new(Mutex::new(String::from("1")));
+	let (r, w) = (value.clone(), value.clone());
+
+	let mut tree = VirtualTree::new();
+	tree.insert("/version", VirtualNode::file("1.0\n"))
+		.insert("/sys/value", VirtualNode::generated(move || Ok(r.lock().unwrap().clone().into_bytes())))
+		.insert(
+			"/sys/ctl",
+			VirtualNode::control(move |data| {
+				*w.lock().unwrap() = String::from_utf8_lossy(data).into_owned();
+				Ok(())
+			})
+		)
+		.insert("/latest", VirtualNode::symlink("version"));
+
+	let req = Request::new(0, 0, 0o022);
+	let root = tree.getattr(&req, Path::new("/")).unwrap();
+	assert_eq!((root.ino, root.kind, root.nlink), (1, FileType::Directory, 3));
+	assert_eq!(tree.getattr(&req, Path::new("/version")).unwrap().size, 4);
+	assert_eq!(tree.getattr(&req, Path::new("/sys/ctl")).unwrap().perm, 0o200);
+	assert_eq!(tree.readlink(&req, Path::new("/latest")).unwrap(), Path::new("version"));
+	assert_eq!(
+		tree.getattr(&req, Path::new("/version/x")).unwrap_err().raw_os_error(),
+		Some(libc::ENOTDIR)
+	);
+
+	assert_eq!(read_all(&mut tree, &req, "/version"), b"1.0\n");
+	assert_eq!(read_all(&mut tree, &req, "/sys/value"), b"1");
+
+	let path = Path::new("/sys/ctl");
+	let mut info = FileInfo::new(OpenFlags::from_bits(libc::O_WRONLY | libc::O_TRUNC));
+	let mut fh = tree.open(&req, path, &mut info).unwrap();
+	tree.truncate(&req, path, 0).unwrap();
+	assert_eq!(tree.write(&req, path, 0, b"2", &mut fh, &info).unwrap(), 1);
+	assert_eq!(read_all(&mut tree, &req, "/sys/value"), b"2");
+
+	let err = tree
+		.open(&req, Path::new("/version"), &mut info)
+		.unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::EACCES));
+}
+
+#[test]
+fn with_clock() {
+	let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+	let clock = Arc::new(ManualClock::new(start));
+	let mut tree = VirtualTree::with_clock(clock.clone());
+	tree.insert("/ctl", VirtualNode::control(|_| Ok(())));
+
+	let req = Request::new(0, 0, 0o022).with_clock(tree.clock());
+	let path = Path::new("/ctl");
+	assert_eq!(tree.getattr(&req, Path::new("/")).unwrap().mtime, start);
+	assert_eq!(tree.getattr(&req, path).unwrap().mtime, start);
+
+	clock.advance(Duration::from_secs(5));
+	let mut info = FileInfo::new(OpenFlags::from_bits(libc::O_WRONLY));
+	let mut fh = tree.open(&req, path, &mut info).unwrap();
+	tree.write(&req, path, 0, b"x", &mut fh, &info).unwrap();
+	assert_eq!(tree.getattr(&req, path).unwrap().mtime, start + Duration::from_secs(5));
+}
+
+#[cfg(feature = "derive")]
+mod derive {
+	use super::*;
+
+	#[derive(FuseDir)]
+	struct Config {
+		#[fuse(perm = 0o600)]
+		level:   u32,
+		#[fuse(readonly)]
+		version: String,
+		#[fuse(dir, rename = "net")]
+		network: Network,
+		#[fuse(skip)]
+		_secret: Vec<u8>,
+	}
+
+	#[derive(FuseDir)]
+	#[fuse(readonly)]
+	struct Network {
+		port: u16,
+	}
+
+	// doesn't expose anything, but must not warn about unused parameters
+	#[derive(FuseDir)]
+	struct Hidden {
+		#[fuse(skip)]
+		_token: u64,
+	}
+
+	#[test]
+	fn fuse_dir() {
+		let config = Arc::new(Mutex::new(Config {
+			level:   1,
+			version: "1.0".into(),
+			network: Network { port: 80 },
+			_secret: Vec::new(),
+		}));
+		let mut tree = VirtualTree::new();
+		tree.insert_dir("/config", &config)
+			.insert_dir("/hidden", &Arc::new(Mutex::new(Hidden { _token: 0 })));
+
+		let req = Request::new(0, 0, 0o022);
+		assert_eq!(tree.getattr(&req, Path::new("/config/level")).unwrap().perm, 0o600);
+		assert_eq!(tree.getattr(&req, Path::new("/config/version")).unwrap().perm, 0o444);
+		assert_eq!(tree.getattr(&req, Path::new("/config/net")).unwrap().kind, FileType::Directory);
+		assert!(tree.getattr(&req, Path::new("/config/_secret")).is_err());
+		assert_eq!(tree.getattr(&req, Path::new("/hidden")).unwrap().nlink, 2);
+		assert_eq!(read_all(&mut tree, &req, "/config/version"), b"1.0\n");
+		assert_eq!(read_all(&mut tree, &req, "/config/net/port"), b"80\n");
+
+		let path = Path::new("/config/level");
+		let mut info = FileInfo::new(OpenFlags::from_bits(libc::O_WRONLY));
+		let mut fh = tree.open(&req, path, &mut info).unwrap();
+		tree.write(&req, path, 0, b"5\n", &mut fh, &info).unwrap();
+		assert_eq!(config.lock().unwrap().level, 5);
+		assert_eq!(read_all(&mut tree, &req, "/config/level"), b"5\n");
+
+		let err = tree
+			.write(&req, path, 0, b"five", &mut fh, &info)
+			.unwrap_err();
+		assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+		let err = tree
+			.open(&req, Path::new("/config/net/port"), &mut info)
+			.unwrap_err();
+		assert_eq!(err.raw_os_error(), Some(libc::EACCES));
+	}
+}